@@ -0,0 +1,279 @@
+use std::{env, fs, io::{self, Write}, path::{Path, PathBuf}, process::Command, sync::{atomic::{AtomicBool, Ordering}, Mutex}};
+use glob::glob;
+use hyperpolyglot::Language;
+use log::{error, info, warn};
+use owo_colors::{OwoColorize, Stream::Stdout};
+
+use crate::{cache::Cache, compiler::{Action, Generic, Job, Platform, Registry}, config::Project, depfile, diagnostics::{self, Diagnostic}, executor::{self, Options, Task}, hermetic::{self, Hermetic}, remote::Remote, structs::Target, util};
+
+/// Builds for each of `triples` in turn, or for the host when there are none, and then prints
+/// the result per triple. After a failure the remaining triples are skipped unless
+/// `keep_going` is set.
+pub fn build_matrix(project: &Project, name: Option<&str>, force: bool, hermetic: bool, options: Options, triples: &[String]) -> bool {
+    if triples.is_empty() {
+        return build(project, name, force, hermetic, options, None)
+    }
+
+    let mut results = Vec::new();
+    for triple in triples {
+        if results.iter().any(|(_, result)| *result == "failed") && !options.keep_going {
+            results.push((triple, "skipped"));
+            continue;
+        }
+        println!("{}", format!("Target triple: {}", triple).if_supports_color(Stdout, |text| text.cyan()));
+        let platform = Platform { triple, toolchain: project.config.toolchains.get(triple) };
+        let success = build(project, name, force, hermetic, options, Some(&platform));
+        results.push((triple, if success { "ok" } else { "failed" }));
+    }
+
+    let width = triples.iter().map(String::len).max().unwrap_or_default().max("Triple".len());
+    println!();
+    println!("{:width$}  {:7}  Output", "Triple", "Result");
+    for (triple, result) in &results {
+        let line = format!("{:width$}  {:7}  {}", triple, result, project.artifact_dir(Some(triple)).display());
+        match *result {
+            "ok" => println!("{}", line.if_supports_color(Stdout, |text| text.green())),
+            "failed" => println!("{}", line.if_supports_color(Stdout, |text| text.red())),
+            _ => println!("{}", line.if_supports_color(Stdout, |text| text.yellow()))
+        }
+    }
+    results.iter().all(|(_, result)| *result == "ok")
+}
+
+/// Compiles every target in the configuration, or only `name` when given, running independent
+/// actions in parallel and skipping those the cache knows to be up to date. `platform` is the
+/// triple to cross-compile for, if not the host. With `hermetic` every target builds as if it
+/// set `hermetic`.
+pub fn build(project: &Project, name: Option<&str>, force: bool, hermetic: bool, options: Options, platform: Option<&Platform>) -> bool {
+    let config = &project.config;
+    let targets: Vec<&Target> = match name {
+        Some(name) => match config.targets.iter().find(|t| t.name == name) {
+            Some(target) => vec![target],
+            None => {
+                error!("No target named {} in the configuration", name);
+                println!("{}", format!("Unknown target: {}", name).if_supports_color(Stdout, |text| text.red()));
+                return false
+            }
+        },
+        None => config.targets.iter().collect()
+    };
+
+    if targets.is_empty() {
+        println!("{}", "No targets to build.".if_supports_color(Stdout, |text| text.yellow()));
+        return true
+    }
+
+    let registry = Registry::new(config);
+    let remote = match Remote::configure(config.cache.as_ref()) {
+        Ok(remote) => remote,
+        Err(err) => {
+            error!("Invalid remote cache: {}", err);
+            println!("{}", format!("Invalid remote cache: {}", err).if_supports_color(Stdout, |text| text.red()));
+            return false
+        }
+    };
+    let cache = &Cache::new(force, project.profile.env.keys().cloned().collect(), remote);
+    let ran = &targets.iter().map(|_| AtomicBool::new(false)).collect::<Vec<_>>();
+    let declared = &targets.iter().map(|target| declared_files(target)).collect::<Vec<_>>();
+    let keep = &project.profile.env.keys().cloned().chain(["CATALYST_PROFILE".to_string()]).collect::<Vec<_>>();
+    let diagnostics: &Mutex<Vec<Diagnostic>> = &Mutex::new(Vec::new());
+    let mut tasks = Vec::new();
+    let mut planned = true;
+    // Which task writes each output, so that actions reading it wait for that task.
+    let mut producers: Vec<(PathBuf, usize)> = Vec::new();
+
+    for (t, target) in targets.iter().enumerate() {
+        info!("{}", format!("Planning target: {}", target.name).if_supports_color(Stdout, |text| text.cyan()));
+        let actions = match plan(project, &registry, target, platform) {
+            Ok(actions) => actions,
+            Err(err) => {
+                fail(&target.name, &err);
+                planned = false;
+                if options.keep_going {
+                    continue;
+                }
+                return false
+            }
+        };
+
+        let mut own = Vec::new();
+        for mut action in actions {
+            action.hermetic = hermetic || target.hermetic;
+            let deps = producers.iter().filter(|(output, _)| action.inputs.contains(output)).map(|(_, task)| *task).collect();
+            producers.extend(action.outputs.iter().map(|output| (output.clone(), tasks.len())));
+            own.push(tasks.len());
+            let name = target.name.clone();
+            tasks.push(Task::new(target.name.clone(), deps, move || {
+                if cache.is_fresh(&action) {
+                    info!("Up to date: {:?}", action.command());
+                    diagnostics.lock().unwrap().extend(cache.diagnostics(&action));
+                    return Ok(())
+                }
+                if cache.restore(&action) {
+                    diagnostics.lock().unwrap().extend(cache.diagnostics(&action));
+                    ran[t].store(true, Ordering::Relaxed);
+                    return Ok(())
+                }
+                for output in &action.outputs {
+                    if let Some(parent) = output.parent() {
+                        fs::create_dir_all(parent).map_err(|err| format!("cannot create {}: {}", parent.display(), err)).inspect_err(|err| fail(&name, err))?;
+                    }
+                }
+                let mut discovered = Vec::new();
+                let mut output = String::new();
+                let mut found = Vec::new();
+                let mut collect = |output: &str| {
+                    found = diagnostics::parse(&name, output);
+                    diagnostics.lock().unwrap().extend(found.iter().cloned());
+                };
+                if action.hermetic {
+                    let sandbox = Hermetic::new(&action, &declared[t], keep).inspect_err(|err| fail(&name, err))?;
+                    let result = run(sandbox.command(), &mut output);
+                    collect(&output);
+                    result.map_err(|err| sandbox.explain(err)).inspect_err(|err| fail(&name, err))?;
+                    // What a traced action read outside the project, such as system libraries,
+                    // is left out of the cache record.
+                    let cwd = env::current_dir().unwrap_or_default();
+                    discovered.extend(sandbox.reads().into_iter().filter(|path| path.is_relative() || path.starts_with(&cwd)));
+                } else {
+                    let result = run(action.command(), &mut output);
+                    collect(&output);
+                    result.inspect_err(|err| fail(&name, err))?;
+                }
+                if let Some(path) = &action.depfile {
+                    discovered.extend(depfile::read(path).unwrap_or_else(|err| {
+                        warn!("Cannot read {}: {}", path.display(), err);
+                        Vec::new()
+                    }));
+                }
+                if action.hermetic {
+                    let undeclared = hermetic::undeclared(&discovered, &declared[t], &action);
+                    if !undeclared.is_empty() {
+                        let list = undeclared.iter().map(|path| path.display().to_string()).collect::<Vec<_>>().join(", ");
+                        warn!("{} reads undeclared inputs: {}", name, list);
+                        println!("{}", format!("{} reads files it does not declare: {}; list them under `inputs`", name, list).if_supports_color(Stdout, |text| text.yellow()));
+                    }
+                }
+                cache.record(&action, &discovered, found);
+                ran[t].store(true, Ordering::Relaxed);
+                Ok(())
+            }));
+        }
+
+        let name = target.name.clone();
+        tasks.push(Task::new(target.name.clone(), own, move || {
+            if ran[t].load(Ordering::Relaxed) {
+                println!("{}", format!("Built {}", name).if_supports_color(Stdout, |text| text.green()));
+            } else {
+                println!("{}", format!("{} is up to date", name).if_supports_color(Stdout, |text| text.green()));
+            }
+            Ok(())
+        }));
+    }
+
+    let outcome = executor::execute(tasks, options);
+    let diagnostics = diagnostics.lock().unwrap();
+    diagnostics::summarize(&diagnostics);
+    diagnostics::write(&project.artifact_dir(platform.map(|platform| platform.triple)), &diagnostics);
+    outcome.summarize();
+    planned && outcome.success()
+}
+
+fn fail(target: &str, err: &str) {
+    error!("Failed to build {}: {}", target, err);
+    println!("{}", format!("Failed to build {}: {}", target, err).if_supports_color(Stdout, |text| text.red()));
+}
+
+/// The actions building `target` for `platform`, or the host, in an order where each only
+/// reads what earlier ones wrote.
+pub fn plan(project: &Project, registry: &Registry, target: &Target, platform: Option<&Platform>) -> Result<Vec<Action>, String> {
+    let sources = expand_sources(&target.sources)?;
+    let (language, found) = match &target.language {
+        Some(language) => (language.clone(), registry.get(language)),
+        None => {
+            let language = detect_language(&sources[0])?;
+            (language.name.to_string(), registry.find(language))
+        }
+    };
+
+    let generic = Generic(&language);
+    let driver = match (found, &target.compiler) {
+        (Some(driver), _) => driver,
+        (None, Some(_)) => &generic,
+        (None, None) => return Err(format!("no driver for {}, set `compiler` on the target or add one under `drivers`", language))
+    };
+    let program = driver.toolchain(target.compiler.as_deref(), platform.and_then(Platform::prefix))?;
+    info!("Using {} for {}", program.display(), language);
+
+    // Each triple gets its own directory, so that the outputs of a matrix do not overwrite each other.
+    let artifacts = project.artifact_dir(platform.map(|platform| platform.triple));
+    let output = match &target.output {
+        Some(output) if !util::contained(Path::new(output)) => return Err(format!("`output` {} must be a relative path inside the output directory", output)),
+        Some(output) => artifacts.join(output),
+        None => artifacts.join(driver.output_name(&target.name, target.kind))
+    };
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent).map_err(|err| format!("cannot create {}: {}", parent.display(), err))?;
+    }
+
+    let mut flags = driver.profile_flags(project.profile.optimize, project.profile.debug_info);
+    flags.extend(project.profile.flags.iter().cloned());
+    let mut env = Vec::new();
+    if let Some(platform) = platform {
+        if let Some(toolchain) = platform.toolchain {
+            flags.extend(toolchain.flags.iter().cloned());
+            env.extend(toolchain.env.iter().map(|(key, value)| (key.clone(), value.clone())));
+        }
+        flags.extend(driver.cross_flags(&program, platform)?);
+        env.extend(driver.cross_env(platform));
+    }
+    flags.extend(target.flags.iter().cloned());
+
+    let job = Job { name: &target.name, kind: target.kind, flags: &flags, sources: &sources, output: &output, platform };
+    let mut actions = driver.actions(&program, &job)?;
+    for action in &mut actions {
+        action.env = env.clone();
+    }
+    Ok(actions)
+}
+
+/// The files `target` declares it reads, in `sources` and `inputs`.
+fn declared_files(target: &Target) -> Vec<PathBuf> {
+    let patterns = target.sources.iter().chain(&target.inputs);
+    patterns.filter_map(|pattern| glob(pattern).ok()).flatten().flatten().filter(|path| path.is_file()).collect()
+}
+
+fn expand_sources(patterns: &[String]) -> Result<Vec<PathBuf>, String> {
+    let mut sources = Vec::new();
+    for pattern in patterns {
+        let paths = glob(pattern).map_err(|err| format!("invalid source pattern {}: {}", pattern, err))?;
+        sources.extend(paths.flatten().filter(|path| path.is_file()));
+    }
+    if sources.is_empty() {
+        return Err("no source files matched".to_string())
+    }
+    Ok(sources)
+}
+
+fn detect_language(source: &Path) -> Result<Language, String> {
+    match hyperpolyglot::detect(source).map(|detection| detection.and_then(|detection| Language::try_from(detection.language()).ok())) {
+        Ok(Some(language)) => Ok(language),
+        _ => Err(format!("cannot detect the language of {}, set `language` on the target", source.display()))
+    }
+}
+
+/// Runs `command` to completion, then echoes what it printed, which is also left in `output`
+/// for the diagnostics. Capturing keeps the output of parallel actions from interleaving.
+fn run(mut command: Command, output: &mut String) -> Result<(), String> {
+    info!("Running: {:?}", command);
+    let program = command.get_program().to_string_lossy().to_string();
+    let result = command.output().map_err(|err| format!("cannot run {}: {}", program, err))?;
+    let _ = io::stdout().lock().write_all(&result.stdout);
+    let _ = io::stderr().lock().write_all(&result.stderr);
+    output.push_str(&String::from_utf8_lossy(&result.stdout));
+    output.push_str(&String::from_utf8_lossy(&result.stderr));
+    match result.status {
+        status if status.success() => Ok(()),
+        status => Err(format!("{} exited with {}", program, status))
+    }
+}
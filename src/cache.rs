@@ -0,0 +1,316 @@
+use std::{env, fs, io::{self, Read}, path::{Path, PathBuf}, time::UNIX_EPOCH};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{compiler::Action, diagnostics::Diagnostic, remote::Remote, util};
+
+/// Variables that change what a compiler produces, hashed with each action on top of those
+/// the active profile sets. `PATH` is only part of the local key, since it differs between
+/// machines.
+const ENV: [&str; 13] = [
+    "CATALYST_PROFILE", "CC", "CXX", "CFLAGS", "CXXFLAGS", "CPPFLAGS", "LDFLAGS",
+    "CPATH", "LIBRARY_PATH", "RUSTFLAGS", "GOFLAGS", "GOOS", "GOARCH"
+];
+
+/// What a file looked like when an action last ran.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct FileState {
+    pub path: PathBuf,
+    /// Nanoseconds since the Unix epoch.
+    pub modified: u64,
+    pub size: u64,
+    pub hash: String
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct Record {
+    pub inputs: Vec<FileState>,
+    pub outputs: Vec<FileState>,
+    /// What the compiler reported, shown again whenever the action is skipped.
+    #[serde(default)]
+    pub diagnostics: Vec<Diagnostic>
+}
+
+/// A file as the remote cache knows it, with paths under the working directory made relative.
+#[derive(Serialize, Deserialize)]
+struct SharedFile {
+    path: PathBuf,
+    hash: String,
+    #[serde(default)]
+    executable: bool
+}
+
+/// What the remote cache keeps for an action: the inputs it read, the outputs it wrote and
+/// what the compiler reported.
+#[derive(Serialize, Deserialize)]
+struct SharedRecord {
+    inputs: Vec<SharedFile>,
+    outputs: Vec<SharedFile>,
+    #[serde(default)]
+    diagnostics: Vec<Diagnostic>
+}
+
+/// Remembers the actions that ran, so that unchanged ones are skipped. Lives in
+/// `~/.catalyst/cache/actions`, one record per action, and optionally in a remote cache that
+/// also holds the outputs.
+pub struct Cache {
+    dir: PathBuf,
+    force: bool,
+    env: Vec<String>,
+    remote: Option<Remote>
+}
+
+impl Cache {
+    /// `env` names the variables set by the active profile. With `force` every action runs,
+    /// but still leaves a record for the next build.
+    pub fn new(force: bool, env: Vec<String>, remote: Option<Remote>) -> Cache {
+        Cache { dir: util::cache_dir().join("actions"), force, env, remote }
+    }
+
+    /// Identifies an action by its command line, working directory and environment.
+    pub fn key(&self, action: &Action) -> String {
+        let mut hasher = self.hasher(action, &action.args);
+        hasher.update([1]);
+        hasher.update(env::current_dir().unwrap_or_default().to_string_lossy().as_bytes());
+        hasher.update([1]);
+        hasher.update(format!("PATH={}", env::var("PATH").unwrap_or_default()).as_bytes());
+        hex(&hasher.finalize())
+    }
+
+    /// Identifies an action the same way on every machine: paths under the working directory
+    /// are made relative, and neither the directory nor `PATH` are part of it.
+    fn shared_key(&self, action: &Action) -> String {
+        let args = action.args.iter().map(|arg| relative_arg(arg)).collect::<Vec<_>>();
+        hex(&self.hasher(action, &args).finalize())
+    }
+
+    fn hasher(&self, action: &Action, args: &[String]) -> Sha256 {
+        let mut hasher = Sha256::new();
+        hasher.update(action.program.to_string_lossy().as_bytes());
+        for arg in args {
+            hasher.update([0]);
+            hasher.update(arg.as_bytes());
+        }
+        hasher.update([action.hermetic as u8]);
+        for (key, value) in &action.env {
+            hasher.update([2]);
+            hasher.update(format!("{}={}", key, value).as_bytes());
+        }
+        let mut names = ENV.iter().map(|name| name.to_string()).chain(self.env.iter().cloned()).collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+        for name in names {
+            hasher.update([1]);
+            hasher.update(format!("{}={}", name, env::var(&name).unwrap_or_default()).as_bytes());
+        }
+        hasher
+    }
+
+    /// Whether `action` ran before with the same inputs, and left its outputs untouched since.
+    pub fn is_fresh(&self, action: &Action) -> bool {
+        if self.force {
+            return false
+        }
+        let Some(record) = self.load(action) else { return false };
+        let covers = |paths: &[PathBuf], states: &[FileState]| paths.iter().all(|path| states.iter().any(|state| &state.path == path));
+        if !covers(&action.inputs, &record.inputs) || !covers(&action.outputs, &record.outputs) {
+            return false
+        }
+
+        let current = |states: &[FileState]| states.iter().map(|state| file_state(&state.path, Some(state))).collect::<Option<Vec<_>>>();
+        let (Some(inputs), Some(outputs)) = (current(&record.inputs), current(&record.outputs)) else { return false };
+        let same = |now: &[FileState], then: &[FileState]| now.iter().zip(then).all(|(now, then)| now.hash == then.hash);
+        if !same(&inputs, &record.inputs) || !same(&outputs, &record.outputs) {
+            return false
+        }
+        // Touched but unchanged files get their new times, so that they are not hashed again.
+        if inputs != record.inputs || outputs != record.outputs {
+            self.save(action, &Record { inputs, outputs, diagnostics: record.diagnostics });
+        }
+        true
+    }
+
+    pub fn load(&self, action: &Action) -> Option<Record> {
+        let content = fs::read_to_string(self.path(action)).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    /// What the compiler reported the last time `action` ran, here or on the machine it was
+    /// restored from.
+    pub fn diagnostics(&self, action: &Action) -> Vec<Diagnostic> {
+        self.load(action).map(|record| record.diagnostics).unwrap_or_default()
+    }
+
+    /// Fetches the outputs of `action` from the remote cache when it holds them for the current
+    /// inputs. Only the declared outputs are written, and only inside the working directory;
+    /// every one is checked against its hash before any is written.
+    pub fn restore(&self, action: &Action) -> bool {
+        let Some(remote) = self.remote.as_ref().filter(|_| !self.force) else { return false };
+        let key = self.shared_key(action);
+        let Some(record) = remote.get(&format!("ac/{}.json", key)).and_then(|bytes| serde_json::from_slice::<SharedRecord>(&bytes).ok()) else { return false };
+
+        let declared = action.inputs.iter().all(|input| record.inputs.iter().any(|file| file.path == relative(input)));
+        if !declared || !record.inputs.iter().all(|file| hash_file(&file.path).is_ok_and(|hash| hash == file.hash)) {
+            return false
+        }
+
+        let mut blobs = Vec::new();
+        for output in &action.outputs {
+            let path = relative(output);
+            if !util::contained(&path) {
+                return false
+            }
+            let Some(file) = record.outputs.iter().find(|file| file.path == path) else { return false };
+            let Some(bytes) = remote.get(&format!("cas/{}", file.hash)) else { return false };
+            if hex(&Sha256::digest(&bytes)) != file.hash {
+                warn!("Remote cache object {} does not match its hash, ignoring it", file.hash);
+                return false
+            }
+            blobs.push((output, file.executable, bytes));
+        }
+        for (output, executable, bytes) in blobs {
+            if let Err(err) = write_output(output, &bytes, executable) {
+                warn!("Cannot write {}: {}", output.display(), err);
+                return false
+            }
+        }
+        info!("Restored from the remote cache: {:?}", action.command());
+
+        let discovered = record.inputs.iter().map(|file| file.path.clone()).collect::<Vec<_>>();
+        self.record(action, &discovered, record.diagnostics);
+        true
+    }
+
+    /// Stores the state of the action's files after a successful run, and shares the outputs
+    /// when the remote cache is writable. `discovered` are inputs the compiler reported on top
+    /// of the declared ones, and `diagnostics` what it printed about the code.
+    pub fn record(&self, action: &Action, discovered: &[PathBuf], diagnostics: Vec<Diagnostic>) {
+        let previous = self.load(action).unwrap_or_default();
+        let state = |path: &PathBuf, previous: &[FileState]| file_state(path, previous.iter().find(|state| &state.path == path));
+
+        let mut inputs: Vec<FileState> = Vec::new();
+        for path in action.inputs.iter().chain(discovered) {
+            if inputs.iter().any(|state| &state.path == path) {
+                continue;
+            }
+            match state(path, &previous.inputs) {
+                Some(state) => inputs.push(state),
+                // Nothing can be promised about an action whose inputs vanished.
+                None => return
+            }
+        }
+        let outputs = action.outputs.iter().filter_map(|path| state(path, &previous.outputs)).collect();
+        let record = Record { inputs, outputs, diagnostics };
+        self.save(action, &record);
+        self.publish(action, &record);
+    }
+
+    /// Uploads the outputs and then the record, so that a record is never seen without them.
+    /// Actions writing directories or outside the working directory are not shared.
+    fn publish(&self, action: &Action, record: &Record) {
+        let Some(remote) = self.remote.as_ref().filter(|remote| remote.writable) else { return };
+        if record.outputs.len() != action.outputs.len() || !action.outputs.iter().all(|output| output.is_file() && util::contained(&relative(output))) {
+            return
+        }
+        let mut outputs = Vec::new();
+        for state in &record.outputs {
+            let Ok(bytes) = fs::read(&state.path) else { return };
+            if !remote.put(&format!("cas/{}", state.hash), &bytes) {
+                return
+            }
+            outputs.push(SharedFile { path: relative(&state.path), hash: state.hash.clone(), executable: is_executable(&state.path) });
+        }
+        let inputs = record.inputs.iter().map(|state| SharedFile { path: relative(&state.path), hash: state.hash.clone(), executable: false }).collect();
+        let shared = SharedRecord { inputs, outputs, diagnostics: record.diagnostics.clone() };
+        if remote.put(&format!("ac/{}.json", self.shared_key(action)), serde_json::to_string(&shared).unwrap().as_bytes()) {
+            info!("Shared {:?}", action.command());
+        }
+    }
+
+    fn path(&self, action: &Action) -> PathBuf {
+        self.dir.join(format!("{}.json", self.key(action)))
+    }
+
+    fn save(&self, action: &Action, record: &Record) {
+        let path = self.path(action);
+        let written = fs::create_dir_all(&self.dir)
+            .and_then(|_| fs::write(path.with_extension("tmp"), serde_json::to_string(record).unwrap()))
+            .and_then(|_| fs::rename(path.with_extension("tmp"), &path));
+        match written {
+            Ok(_) => info!("Recorded {}", path.display()),
+            Err(err) => warn!("Cannot write {}: {}", path.display(), err)
+        }
+    }
+}
+
+/// The current state of `path`. The hash of `previous` is reused when the modification time
+/// and size still match, so unchanged files are not read again.
+pub fn file_state(path: &Path, previous: Option<&FileState>) -> Option<FileState> {
+    let metadata = fs::metadata(path).ok()?;
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?.as_nanos() as u64;
+    let size = metadata.len();
+    if let Some(previous) = previous.filter(|previous| previous.modified == modified && previous.size == size) {
+        return Some(previous.clone())
+    }
+    let hash = hash_file(path).ok()?;
+    Some(FileState { path: path.to_path_buf(), modified, size, hash })
+}
+
+/// `path` relative to the working directory when it lies inside it.
+fn relative(path: &Path) -> PathBuf {
+    let cwd = env::current_dir().unwrap_or_default();
+    path.strip_prefix(&cwd).map(Path::to_path_buf).unwrap_or(path.to_path_buf())
+}
+
+/// `arg` with the working directory removed from the paths it holds, e.g. `-o/abs/out/x`.
+fn relative_arg(arg: &str) -> String {
+    let cwd = env::current_dir().unwrap_or_default();
+    let cwd = cwd.to_string_lossy();
+    arg.replace(&format!("{}{}", cwd, std::path::MAIN_SEPARATOR), "")
+}
+
+fn write_output(path: &Path, bytes: &[u8], executable: bool) -> io::Result<()> {
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    let tmp = path.with_extension("catalyst-tmp");
+    fs::write(&tmp, bytes)?;
+    #[cfg(unix)]
+    if executable {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&tmp, fs::Permissions::from_mode(0o755))?;
+    }
+    #[cfg(not(unix))]
+    let _ = executable;
+    fs::rename(&tmp, path)
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path).is_ok_and(|metadata| metadata.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(_: &Path) -> bool {
+    false
+}
+
+pub fn hash_file(path: &Path) -> io::Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = [0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hex(&hasher.finalize()))
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
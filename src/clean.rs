@@ -0,0 +1,136 @@
+use std::{fs, path::{Component, Path, PathBuf}};
+use log::{error, info, warn};
+use owo_colors::{OwoColorize, Stream::Stdout};
+use walkdir::WalkDir;
+
+use crate::{config::{Project, CONFIG_DIR, OUT_DIR}, util};
+
+/// What `cly clean` removes.
+pub struct Options {
+    /// Only the artifacts of this triple, `host` for host builds.
+    pub triple: Option<String>,
+    /// Only the artifacts of the active profile.
+    pub profile: bool,
+    pub cache: bool,
+    pub logs: bool,
+    pub dry_run: bool
+}
+
+impl Options {
+    /// Build artifacts are removed unless only `--cache` or `--logs` was asked for.
+    pub fn outputs(&self) -> bool {
+        self.triple.is_some() || self.profile || !(self.cache || self.logs)
+    }
+}
+
+/// The directories of `projects` matching `options`, then the cache and logs if asked for.
+pub fn paths(projects: &[Project], options: &Options) -> Result<Vec<PathBuf>, String> {
+    if let Some(triple) = &options.triple {
+        if Path::new(triple).components().ne([Component::Normal(triple.as_ref())]) {
+            return Err(format!("`{}` is not a target triple", triple))
+        }
+    }
+
+    let mut paths = Vec::new();
+    if options.outputs() {
+        for project in projects {
+            let out = project.root.join(CONFIG_DIR).join(OUT_DIR);
+            let profiles = if options.profile {
+                let dir = project.output_dir();
+                if !dir.exists() {
+                    continue;
+                }
+                if !is_within(&dir, &out) {
+                    warn!("Output directory {} is not managed by Catalyst", dir.display());
+                    println!("{}", format!("Skipping {}, it is outside {}.", dir.display(), out.display()).if_supports_color(Stdout, |text| text.yellow()));
+                    continue;
+                }
+                vec![dir]
+            } else if options.triple.is_none() {
+                vec![out]
+            } else {
+                fs::read_dir(&out).into_iter().flatten().flatten().map(|entry| entry.path()).filter(|path| path.is_dir()).collect()
+            };
+            match &options.triple {
+                Some(triple) => paths.extend(profiles.iter().map(|dir| dir.join(triple))),
+                None => paths.extend(profiles)
+            }
+        }
+    }
+    if options.cache {
+        paths.push(util::cache_dir());
+    }
+    if options.logs {
+        let logs = fs::read_dir(util::logs_dir()).into_iter().flatten().flatten().map(|entry| entry.path());
+        paths.extend(logs.filter(|path| path.file_name().and_then(|name| name.to_str()).is_some_and(|name| name.starts_with("catalyst-") && name.ends_with(".log"))));
+    }
+    paths.retain(|path| path.exists());
+    paths.sort();
+    paths.dedup();
+    Ok(paths)
+}
+
+/// Removes `paths`, or only lists them with `dry_run`, and reports the space freed.
+pub fn clean(paths: &[PathBuf], dry_run: bool) -> bool {
+    if paths.is_empty() {
+        println!("{}", "Nothing to clean.".if_supports_color(Stdout, |text| text.green()));
+        return true
+    }
+
+    let mut freed = 0;
+    let mut success = true;
+    for path in paths {
+        let bytes = size(path);
+        if dry_run {
+            println!("Would remove {} ({})", path.display(), format_size(bytes));
+            freed += bytes;
+            continue;
+        }
+        let removed = if path.is_dir() { fs::remove_dir_all(path) } else { fs::remove_file(path) };
+        match removed {
+            Ok(_) => {
+                info!("Removed {}", path.display());
+                println!("Removed {} ({})", path.display(), format_size(bytes));
+                freed += bytes;
+            }
+            Err(err) => {
+                error!("Cannot remove {}: {}", path.display(), err);
+                println!("{}", format!("Cannot remove {}: {}", path.display(), err).if_supports_color(Stdout, |text| text.red()));
+                success = false;
+            }
+        }
+    }
+    let verb = if dry_run { "Would free" } else { "Freed" };
+    println!("{}", format!("{} {} ({} bytes).", verb, format_size(freed), freed).if_supports_color(Stdout, |text| text.green()));
+    success
+}
+
+/// Whether `path` is `dir` or inside it once symlinks and `..` are resolved.
+fn is_within(path: &Path, dir: &Path) -> bool {
+    match (path.canonicalize(), dir.canonicalize()) {
+        (Ok(path), Ok(dir)) => path.starts_with(dir),
+        _ => false
+    }
+}
+
+fn size(path: &Path) -> u64 {
+    WalkDir::new(path).into_iter().flatten()
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| entry.metadata().ok())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes)
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
@@ -0,0 +1,48 @@
+use std::{env, fs};
+use log::{error, info};
+use owo_colors::{OwoColorize, Stream::Stdout};
+use serde_json::{json, Value};
+
+use crate::{builder, compiler::{Action, Registry}, config::Project};
+
+pub const FILE_NAME: &str = "compile_commands.json";
+
+/// Writes `compile_commands.json` at the project root, with one entry per C or C++ source
+/// compiled exactly as `cly build` would. Expects the project to have been entered, since
+/// compilers run from its working directory.
+pub fn write(project: &Project) -> bool {
+    let registry = Registry::new(&project.config);
+    let directory = env::current_dir().unwrap_or_else(|_| project.working_directory());
+    let mut entries = Vec::new();
+    for target in &project.config.targets {
+        match builder::plan(project, &registry, target, None) {
+            Ok(actions) => entries.extend(actions.iter().filter_map(|action| entry(action, &directory.display().to_string()))),
+            Err(err) => println!("{}", format!("Skipping {} in {}: {}", target.name, FILE_NAME, err).if_supports_color(Stdout, |text| text.yellow()))
+        }
+    }
+
+    let path = project.root.join(FILE_NAME);
+    match fs::write(&path, serde_json::to_string_pretty(&entries).unwrap() + "\n") {
+        Ok(_) => {
+            info!("Wrote {} entries to {}", entries.len(), path.display());
+            println!("{}", format!("Wrote {}", path.display()).if_supports_color(Stdout, |text| text.green()));
+            true
+        }
+        Err(err) => {
+            error!("Cannot write {}: {}", path.display(), err);
+            println!("{}", format!("Cannot write {}: {}", path.display(), err).if_supports_color(Stdout, |text| text.red()));
+            false
+        }
+    }
+}
+
+fn entry(action: &Action, directory: &str) -> Option<Value> {
+    let source = action.source.as_ref()?;
+    let arguments = std::iter::once(action.program.display().to_string()).chain(action.args.iter().cloned()).collect::<Vec<_>>();
+    Some(json!({
+        "directory": directory,
+        "file": source.display().to_string(),
+        "arguments": arguments,
+        "output": action.outputs.first().map(|output| output.display().to_string())
+    }))
+}
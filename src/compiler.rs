@@ -0,0 +1,598 @@
+use std::{env::{self, consts}, fs, path::{Component, Path, PathBuf}, process::Command};
+use hyperpolyglot::Language;
+
+use crate::structs::{Config, DriverConfig, OutputKind, Toolchain};
+
+/// One command of a build, with the files it reads and writes.
+#[derive(Clone, Debug)]
+pub struct Action {
+    pub program: PathBuf,
+    pub args: Vec<String>,
+    pub inputs: Vec<PathBuf>,
+    pub outputs: Vec<PathBuf>,
+    /// Variables set on top of the inherited environment.
+    pub env: Vec<(String, String)>,
+    /// The translation unit, for actions compiling a single C or C++ source.
+    pub source: Option<PathBuf>,
+    /// Where the compiler writes the other files it read, such as headers.
+    pub depfile: Option<PathBuf>,
+    /// Runs with a scrubbed environment, see [`crate::hermetic`].
+    pub hermetic: bool
+}
+
+impl Action {
+    fn new(program: &Path) -> Action {
+        Action { program: program.to_path_buf(), args: Vec::new(), inputs: Vec::new(), outputs: Vec::new(), env: Vec::new(), source: None, depfile: None, hermetic: false }
+    }
+
+    fn arg<S: AsRef<str>>(mut self, arg: S) -> Action {
+        self.args.push(arg.as_ref().to_string());
+        self
+    }
+
+    fn args<I: IntoIterator<Item = S>, S: AsRef<str>>(mut self, args: I) -> Action {
+        self.args.extend(args.into_iter().map(|arg| arg.as_ref().to_string()));
+        self
+    }
+
+    fn input(mut self, path: &Path) -> Action {
+        self.args.push(path.display().to_string());
+        self.inputs.push(path.to_path_buf());
+        self
+    }
+
+    fn inputs(self, paths: &[PathBuf]) -> Action {
+        paths.iter().fold(self, |action, path| action.input(path))
+    }
+
+    /// Records an output without adding it to the arguments, for compilers that take it
+    /// glued to a flag.
+    fn writes(mut self, path: &Path) -> Action {
+        self.outputs.push(path.to_path_buf());
+        self
+    }
+
+    fn output(self, path: &Path) -> Action {
+        self.arg(path.display().to_string()).writes(path)
+    }
+
+    pub fn command(&self) -> Command {
+        let mut command = Command::new(&self.program);
+        command.args(&self.args).envs(self.env.iter().map(|(key, value)| (key, value)));
+        command
+    }
+}
+
+/// The target settings a driver turns into actions.
+pub struct Job<'a> {
+    pub name: &'a str,
+    pub kind: OutputKind,
+    pub flags: &'a [String],
+    pub sources: &'a [PathBuf],
+    pub output: &'a Path,
+    /// Set when cross-compiling.
+    pub platform: Option<&'a Platform<'a>>
+}
+
+/// A target triple other than the host, and its configured toolchain if any.
+pub struct Platform<'a> {
+    pub triple: &'a str,
+    pub toolchain: Option<&'a Toolchain>
+}
+
+impl Platform<'_> {
+    pub fn prefix(&self) -> Option<&str> {
+        self.toolchain.and_then(|toolchain| toolchain.prefix.as_deref())
+    }
+
+    pub fn sysroot(&self) -> Option<&str> {
+        self.toolchain.and_then(|toolchain| toolchain.sysroot.as_deref())
+    }
+
+    /// Whether the triple names the machine Catalyst runs on.
+    pub fn is_host(&self) -> bool {
+        let (arch, os) = self.arch_os();
+        arch == consts::ARCH && (os == consts::OS || (os == "darwin" && consts::OS == "macos"))
+    }
+
+    /// The triple's architecture and operating system, skipping the vendor when there is one.
+    fn arch_os(&self) -> (&str, &str) {
+        let parts = self.triple.split('-').collect::<Vec<_>>();
+        match parts.as_slice() {
+            [arch, _, os, ..] => (arch, os),
+            [arch, os] => (arch, os),
+            _ => (self.triple, "")
+        }
+    }
+}
+
+/// Knows how to compile one language.
+pub trait Driver {
+    /// The language as named by hyperpolyglot, e.g. `C++`.
+    fn language(&self) -> &str;
+
+    /// Programs tried in order when neither the target nor the environment names one.
+    fn candidates(&self) -> Vec<String>;
+
+    /// Environment variable naming the compiler, such as `CC`.
+    fn env_var(&self) -> Option<&str> {
+        None
+    }
+
+    /// The language's spelling of the profile's `optimize` and `debug_info` switches.
+    fn profile_flags(&self, optimize: bool, debug_info: bool) -> Vec<String>;
+
+    fn output_name(&self, name: &str, kind: OutputKind) -> String {
+        default_output_name(name, kind)
+    }
+
+    /// Flags selecting `platform`, for compilers that build for several triples.
+    fn cross_flags(&self, _program: &Path, _platform: &Platform) -> Result<Vec<String>, String> {
+        Ok(Vec::new())
+    }
+
+    /// Variables selecting `platform`, for compilers configured through the environment.
+    fn cross_env(&self, _platform: &Platform) -> Vec<(String, String)> {
+        Vec::new()
+    }
+
+    fn actions(&self, program: &Path, job: &Job) -> Result<Vec<Action>, String>;
+
+    /// Finds the compiler: the target's `compiler`, then the environment variable, then the
+    /// first candidate on `PATH`. With a toolchain `prefix` the environment variable, which
+    /// names the host compiler, is skipped and the candidates are looked up with the prefix.
+    fn toolchain(&self, compiler: Option<&str>, prefix: Option<&str>) -> Result<PathBuf, String> {
+        if let Some(compiler) = compiler {
+            return which(compiler).ok_or(format!("compiler {} not found", compiler))
+        }
+        if let (Some(var), None) = (self.env_var(), prefix) {
+            if let Ok(compiler) = env::var(var) {
+                return which(&compiler).ok_or(format!("compiler {} from {} not found", compiler, var))
+            }
+        }
+        let candidates = self.candidates().into_iter().map(|candidate| format!("{}{}", prefix.unwrap_or_default(), candidate)).collect::<Vec<_>>();
+        candidates.iter().find_map(|candidate| which(candidate)).ok_or(format!(
+            "no {} compiler found, tried {}; set `compiler` on the target{}",
+            self.language(),
+            candidates.join(", "),
+            self.env_var().map(|var| format!(" or {}", var)).unwrap_or_default()
+        ))
+    }
+}
+
+/// The built-in drivers and those declared under `drivers`, which take precedence.
+pub struct Registry {
+    drivers: Vec<Box<dyn Driver>>
+}
+
+impl Registry {
+    pub fn builtin() -> Registry {
+        Registry {
+            drivers: vec![
+                Box::new(Cc { language: "C", env_var: "CC", candidates: &["cc", "gcc", "clang"] }),
+                Box::new(Cc { language: "C++", env_var: "CXX", candidates: &["c++", "g++", "clang++"] }),
+                Box::new(Rustc),
+                Box::new(Go),
+                Box::new(Javac),
+                Box::new(Zig)
+            ]
+        }
+    }
+
+    pub fn new(config: &Config) -> Registry {
+        let mut registry = Registry::builtin();
+        for driver in config.drivers.iter().rev() {
+            registry.drivers.insert(0, Box::new(Custom(driver.clone())));
+        }
+        registry
+    }
+
+    /// The driver for `language`, or for the language it is grouped under.
+    pub fn find(&self, language: Language) -> Option<&dyn Driver> {
+        let driver = self.drivers.iter().find(|driver| Language::try_from(driver.language()) == Ok(language));
+        driver.map(|driver| driver.as_ref()).or_else(|| self.get(language.group?))
+    }
+
+    /// The driver for the language named `language`, also for languages hyperpolyglot does not know.
+    pub fn get(&self, language: &str) -> Option<&dyn Driver> {
+        match Language::try_from(language).ok().and_then(|language| self.find(language)) {
+            Some(driver) => Some(driver),
+            None => self.drivers.iter().find(|driver| driver.language() == language).map(|driver| driver.as_ref())
+        }
+    }
+}
+
+/// Runs a compiler Catalyst knows nothing about as `compiler flags sources -o output`.
+pub struct Generic<'a>(pub &'a str);
+
+impl Driver for Generic<'_> {
+    fn language(&self) -> &str {
+        self.0
+    }
+
+    fn candidates(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn profile_flags(&self, _: bool, _: bool) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn actions(&self, program: &Path, job: &Job) -> Result<Vec<Action>, String> {
+        Ok(vec![Action::new(program).args(job.flags).inputs(job.sources).arg("-o").output(job.output)])
+    }
+}
+
+struct Cc {
+    language: &'static str,
+    env_var: &'static str,
+    candidates: &'static [&'static str]
+}
+
+impl Driver for Cc {
+    fn language(&self) -> &str {
+        self.language
+    }
+
+    fn candidates(&self) -> Vec<String> {
+        self.candidates.iter().map(|candidate| candidate.to_string()).collect()
+    }
+
+    fn env_var(&self) -> Option<&str> {
+        Some(self.env_var)
+    }
+
+    fn profile_flags(&self, optimize: bool, debug_info: bool) -> Vec<String> {
+        switches(optimize, &["-O2"], debug_info, &["-g"])
+    }
+
+    /// Clang builds for any triple given `--target`; gcc needs a prefixed cross compiler.
+    fn cross_flags(&self, program: &Path, platform: &Platform) -> Result<Vec<String>, String> {
+        let mut flags = Vec::new();
+        if program.file_name().is_some_and(|name| name.to_string_lossy().contains("clang")) {
+            flags.push(format!("--target={}", platform.triple));
+        } else if platform.prefix().is_none() && !platform.is_host() {
+            return Err(format!(
+                "{} cannot build for {}; set `toolchains.{}.prefix` to use a cross compiler, or use clang",
+                program.display(), platform.triple, platform.triple
+            ))
+        }
+        if let Some(sysroot) = platform.sysroot() {
+            flags.push(format!("--sysroot={}", sysroot));
+        }
+        Ok(flags)
+    }
+
+    /// Compiles each source to its own object, recording the headers it includes in a depfile,
+    /// so that editing a header only recompiles the sources including it. The objects are then
+    /// linked, or archived with `ar` for static libraries.
+    fn actions(&self, program: &Path, job: &Job) -> Result<Vec<Action>, String> {
+        let objects_dir = job.output.with_file_name(format!("{}.objs", job.name));
+        let mut actions = Vec::new();
+        let mut objects = Vec::new();
+        for source in job.sources {
+            let object = object_path(&objects_dir, source);
+            let depfile = object.with_extension("d");
+            let mut compile = Action::new(program);
+            if job.kind == OutputKind::SharedLib {
+                compile = compile.arg("-fPIC");
+            }
+            let mut compile = compile.args(job.flags)
+                .args(["-MD", "-MF", &depfile.display().to_string()])
+                .arg("-c")
+                .input(source)
+                .arg("-o")
+                .output(&object);
+            compile.source = Some(source.clone());
+            compile.depfile = Some(depfile);
+            actions.push(compile);
+            objects.push(object);
+        }
+
+        let link = match job.kind {
+            OutputKind::StaticLib => {
+                let ar = format!("{}ar", job.platform.and_then(Platform::prefix).unwrap_or_default());
+                let ar = which(&ar).ok_or(format!("{} not found, it is needed for static libraries", ar))?;
+                Action::new(&ar).arg("rcs").output(job.output).inputs(&objects)
+            }
+            OutputKind::SharedLib => Action::new(program).arg("-shared").args(job.flags).inputs(&objects).arg("-o").output(job.output),
+            OutputKind::Executable => Action::new(program).args(job.flags).inputs(&objects).arg("-o").output(job.output)
+        };
+        actions.push(link);
+        Ok(actions)
+    }
+}
+
+/// Where the object of `source` goes under `dir`, mirroring its path so that sources with
+/// the same name in different directories do not collide.
+fn object_path(dir: &Path, source: &Path) -> PathBuf {
+    let mut object = dir.to_path_buf();
+    for component in source.components() {
+        match component {
+            Component::Normal(part) => object.push(part),
+            Component::ParentDir => object.push("__"),
+            _ => {}
+        }
+    }
+    object.as_mut_os_string().push(".o");
+    object
+}
+
+struct Rustc;
+
+impl Driver for Rustc {
+    fn language(&self) -> &str {
+        "Rust"
+    }
+
+    fn candidates(&self) -> Vec<String> {
+        vec!["rustc".to_string()]
+    }
+
+    fn env_var(&self) -> Option<&str> {
+        Some("RUSTC")
+    }
+
+    fn profile_flags(&self, optimize: bool, debug_info: bool) -> Vec<String> {
+        switches(optimize, &["-C", "opt-level=2"], debug_info, &["-g"])
+    }
+
+    /// Links with the prefixed C compiler, since the host linker cannot link for the triple.
+    fn cross_flags(&self, _: &Path, platform: &Platform) -> Result<Vec<String>, String> {
+        let mut flags = vec!["--target".to_string(), platform.triple.to_string()];
+        if let Some(prefix) = platform.prefix() {
+            flags.extend(["-C".to_string(), format!("linker={}gcc", prefix)]);
+        }
+        Ok(flags)
+    }
+
+    /// rustc compiles the crate root, the first source, and finds the modules itself. The other
+    /// sources are still inputs of the build.
+    fn actions(&self, program: &Path, job: &Job) -> Result<Vec<Action>, String> {
+        let crate_type = match job.kind {
+            OutputKind::Executable => "bin",
+            OutputKind::StaticLib => "staticlib",
+            OutputKind::SharedLib => "cdylib"
+        };
+        let action = Action::new(program)
+            .args(["--crate-type", crate_type, "--crate-name", &job.name.replace('-', "_")])
+            .args(job.flags)
+            .input(&job.sources[0])
+            .arg("-o")
+            .output(job.output);
+        let inputs = [action.inputs.clone(), job.sources[1..].to_vec()].concat();
+        Ok(vec![Action { inputs, ..action }])
+    }
+}
+
+struct Go;
+
+impl Driver for Go {
+    fn language(&self) -> &str {
+        "Go"
+    }
+
+    fn candidates(&self) -> Vec<String> {
+        vec!["go".to_string()]
+    }
+
+    fn profile_flags(&self, _: bool, debug_info: bool) -> Vec<String> {
+        switches(false, &[], debug_info, &["-gcflags=all=-N -l"])
+    }
+
+    fn cross_env(&self, platform: &Platform) -> Vec<(String, String)> {
+        let (arch, os) = platform.arch_os();
+        let arch = match arch {
+            "x86_64" => "amd64",
+            "aarch64" => "arm64",
+            "i386" | "i586" | "i686" => "386",
+            arch if arch.starts_with("arm") => "arm",
+            arch => arch
+        };
+        let os = match os {
+            "macos" => "darwin",
+            os => os
+        };
+        vec![("GOOS".to_string(), os.to_string()), ("GOARCH".to_string(), arch.to_string())]
+    }
+
+    fn output_name(&self, name: &str, kind: OutputKind) -> String {
+        match kind {
+            // c-archive writes a Unix archive on every platform.
+            OutputKind::StaticLib => format!("lib{}.a", name),
+            kind => default_output_name(name, kind)
+        }
+    }
+
+    fn actions(&self, program: &Path, job: &Job) -> Result<Vec<Action>, String> {
+        let mode = match job.kind {
+            OutputKind::Executable => "default",
+            OutputKind::StaticLib => "c-archive",
+            OutputKind::SharedLib => "c-shared"
+        };
+        let action = Action::new(program)
+            .arg("build")
+            .arg(format!("-buildmode={}", mode))
+            .arg("-o")
+            .output(job.output)
+            .args(job.flags)
+            .inputs(job.sources);
+        Ok(vec![action])
+    }
+}
+
+/// Compiles to classes next to the output and packs them into a jar, runnable when the
+/// target is an executable whose first source holds the main class. The class named after
+/// each source stands for everything javac writes for it, since a directory cannot be cached.
+struct Javac;
+
+impl Driver for Javac {
+    fn language(&self) -> &str {
+        "Java"
+    }
+
+    fn candidates(&self) -> Vec<String> {
+        vec!["javac".to_string()]
+    }
+
+    fn env_var(&self) -> Option<&str> {
+        Some("JAVAC")
+    }
+
+    fn profile_flags(&self, _: bool, debug_info: bool) -> Vec<String> {
+        switches(false, &[], debug_info, &["-g"])
+    }
+
+    fn output_name(&self, name: &str, _: OutputKind) -> String {
+        format!("{}.jar", name)
+    }
+
+    fn actions(&self, program: &Path, job: &Job) -> Result<Vec<Action>, String> {
+        let classes = job.output.with_file_name(format!("{}.classes", job.name));
+        let jar = which(&program.with_file_name(format!("jar{}", consts::EXE_SUFFIX)).display().to_string())
+            .or(which("jar"))
+            .ok_or("jar not found next to javac or on PATH")?;
+
+        let class_files = job.sources.iter().map(|source| class_file(&classes, source)).collect::<Vec<_>>();
+        let compile = class_files.iter().fold(Action::new(program).args(job.flags).arg("-d").arg(classes.display().to_string()).inputs(job.sources), |action, class| action.writes(class));
+        let mut pack = Action::new(&jar).arg("--create").arg("--file").output(job.output);
+        if job.kind == OutputKind::Executable {
+            pack = pack.arg("--main-class").arg(job.sources[0].file_stem().unwrap_or_default().to_string_lossy());
+        }
+        let pack = pack.arg("-C").arg(classes.display().to_string()).arg(".");
+        Ok(vec![compile, Action { inputs: class_files, ..pack }])
+    }
+}
+
+/// Where javac puts the class of `source`: under `classes`, in the directories of the package
+/// the source declares.
+fn class_file(classes: &Path, source: &Path) -> PathBuf {
+    let package = fs::read_to_string(source).ok().and_then(|text| {
+        text.lines().find_map(|line| line.trim().strip_prefix("package ").map(|name| name.trim_end_matches(';').trim().to_string()))
+    });
+    let dir = package.iter().flat_map(|package| package.split('.')).fold(classes.to_path_buf(), |dir, part| dir.join(part));
+    dir.join(source.file_stem().unwrap_or_default()).with_extension("class")
+}
+
+struct Zig;
+
+impl Driver for Zig {
+    fn language(&self) -> &str {
+        "Zig"
+    }
+
+    fn candidates(&self) -> Vec<String> {
+        vec!["zig".to_string()]
+    }
+
+    fn profile_flags(&self, optimize: bool, _: bool) -> Vec<String> {
+        switches(optimize, &["-O", "ReleaseFast"], false, &[])
+    }
+
+    /// Zig spells triples without the vendor, e.g. `aarch64-linux-gnu`.
+    fn cross_flags(&self, _: &Path, platform: &Platform) -> Result<Vec<String>, String> {
+        let parts = platform.triple.split('-').collect::<Vec<_>>();
+        let triple = match parts.as_slice() {
+            [arch, _, rest @ ..] if rest.len() >= 2 => [&[*arch], rest].concat().join("-"),
+            _ => platform.triple.to_string()
+        };
+        Ok(vec!["-target".to_string(), triple])
+    }
+
+    fn actions(&self, program: &Path, job: &Job) -> Result<Vec<Action>, String> {
+        let action = Action::new(program);
+        let action = match job.kind {
+            OutputKind::Executable => action.arg("build-exe"),
+            OutputKind::StaticLib => action.arg("build-lib"),
+            OutputKind::SharedLib => action.args(["build-lib", "-dynamic"])
+        };
+        let action = action.args(job.flags).inputs(job.sources).arg(format!("-femit-bin={}", job.output.display())).writes(job.output);
+        Ok(vec![action])
+    }
+}
+
+/// A driver declared under `drivers`.
+struct Custom(DriverConfig);
+
+impl Driver for Custom {
+    fn language(&self) -> &str {
+        &self.0.language
+    }
+
+    fn candidates(&self) -> Vec<String> {
+        self.0.compilers.clone()
+    }
+
+    fn profile_flags(&self, optimize: bool, debug_info: bool) -> Vec<String> {
+        let mut flags = Vec::new();
+        if optimize {
+            flags.extend(self.0.optimize.iter().cloned());
+        }
+        if debug_info {
+            flags.extend(self.0.debug_info.iter().cloned());
+        }
+        flags
+    }
+
+    fn cross_flags(&self, _: &Path, platform: &Platform) -> Result<Vec<String>, String> {
+        Ok(self.0.cross.iter().map(|arg| arg.replace("{triple}", platform.triple)).collect())
+    }
+
+    fn output_name(&self, name: &str, kind: OutputKind) -> String {
+        match &self.0.output {
+            Some(output) => output.replace("{name}", name),
+            None => default_output_name(name, kind)
+        }
+    }
+
+    fn actions(&self, program: &Path, job: &Job) -> Result<Vec<Action>, String> {
+        let output_dir = job.output.parent().unwrap_or(Path::new(".")).display().to_string();
+        let mut action = Action::new(program).writes(job.output);
+        action.inputs = job.sources.to_vec();
+        for arg in &self.0.args {
+            match arg.as_str() {
+                "{flags}" => action.args.extend(job.flags.iter().cloned()),
+                "{sources}" => action.args.extend(job.sources.iter().map(|source| source.display().to_string())),
+                arg => action.args.push(
+                    arg.replace("{source}", &job.sources[0].display().to_string())
+                        .replace("{output}", &job.output.display().to_string())
+                        .replace("{output_dir}", &output_dir)
+                        .replace("{name}", job.name)
+                )
+            }
+        }
+        Ok(vec![action])
+    }
+}
+
+fn switches(optimize: bool, optimize_flags: &[&str], debug_info: bool, debug_flags: &[&str]) -> Vec<String> {
+    let mut flags = Vec::new();
+    if optimize {
+        flags.extend(optimize_flags.iter().map(|flag| flag.to_string()));
+    }
+    if debug_info {
+        flags.extend(debug_flags.iter().map(|flag| flag.to_string()));
+    }
+    flags
+}
+
+fn default_output_name(name: &str, kind: OutputKind) -> String {
+    match kind {
+        OutputKind::Executable => format!("{}{}", name, consts::EXE_SUFFIX),
+        OutputKind::StaticLib if consts::OS == "windows" => format!("{}.lib", name),
+        OutputKind::StaticLib => format!("lib{}.a", name),
+        OutputKind::SharedLib => format!("{}{}{}", consts::DLL_PREFIX, name, consts::DLL_SUFFIX)
+    }
+}
+
+/// Looks `program` up on `PATH`, unless it is already a path.
+pub fn which(program: &str) -> Option<PathBuf> {
+    let path = Path::new(program);
+    if path.components().count() > 1 {
+        return path.is_file().then(|| path.to_path_buf())
+    }
+    let names = [program.to_string(), format!("{}{}", program, consts::EXE_SUFFIX)];
+    env::split_paths(&env::var_os("PATH")?)
+        .flat_map(|dir| names.iter().map(move |name| dir.join(name)))
+        .find(|candidate| candidate.is_file())
+}
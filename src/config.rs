@@ -0,0 +1,322 @@
+use std::{env, fs, path::{Path, PathBuf}, process};
+use clap::ArgMatches;
+use log::{error, info, warn};
+use owo_colors::{OwoColorize, Stream::Stdout};
+use serde_json::Value;
+
+use crate::{profiles::{self, ActiveProfile}, structs::Config, validate};
+
+/// The directory name of host builds under a profile's output directory.
+pub const HOST: &str = "host";
+/// Holds the output directories of every profile, unless a profile sets its own.
+pub const OUT_DIR: &str = "out";
+pub const CONFIG_DIR: &str = ".catalyst";
+pub const CONFIG_STEM: &str = "config.cly";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Format {
+    Json,
+    Toml,
+    Yaml
+}
+
+impl Format {
+    /// Every format, in order of precedence when several config files sit side by side.
+    pub const ALL: [Format; 3] = [Format::Json, Format::Toml, Format::Yaml];
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Json => "json",
+            Format::Toml => "toml",
+            Format::Yaml => "yaml"
+        }
+    }
+
+    pub fn file_name(self) -> String {
+        format!("{}.{}", CONFIG_STEM, self.extension())
+    }
+
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name {
+            "json" => Some(Format::Json),
+            "toml" => Some(Format::Toml),
+            "yaml" | "yml" => Some(Format::Yaml),
+            _ => None
+        }
+    }
+
+    /// Recognises `*.cly.json`, `*.cly.toml`, `*.cly.yaml` and `*.cly.yml`.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Format> {
+        let name = path.as_ref().file_name()?.to_string_lossy().to_string();
+        let (stem, extension) = name.rsplit_once('.')?;
+        if !stem.ends_with(".cly") {
+            return None
+        }
+        Format::from_name(extension)
+    }
+
+    pub fn parse(self, content: &str) -> Result<Value, String> {
+        match self {
+            Format::Json => serde_json::from_str(content).map_err(|err| err.to_string()),
+            Format::Toml => toml::from_str(content).map_err(|err| err.to_string()),
+            Format::Yaml => serde_yaml::from_str(content).map_err(|err| err.to_string())
+        }
+    }
+
+    pub fn serialize(self, value: &Value) -> Result<String, String> {
+        match self {
+            Format::Json => serde_json::to_string_pretty(value).map_err(|err| err.to_string()),
+            // TOML has no null, absent keys mean the same thing.
+            Format::Toml => toml::to_string_pretty(&strip_nulls(value.clone())).map_err(|err| err.to_string()),
+            Format::Yaml => serde_yaml::to_string(value).map_err(|err| err.to_string())
+        }
+    }
+}
+
+fn strip_nulls(value: Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(map.into_iter().filter(|(_, v)| !v.is_null()).map(|(k, v)| (k, strip_nulls(v))).collect()),
+        Value::Array(items) => Value::Array(items.into_iter().map(strip_nulls).collect()),
+        other => other
+    }
+}
+
+/// A loaded configuration together with the directory it belongs to.
+pub struct Project {
+    /// The directory containing `.catalyst/`.
+    pub root: PathBuf,
+    pub path: PathBuf,
+    pub format: Format,
+    pub config: Config,
+    pub profile: ActiveProfile
+}
+
+impl Project {
+    pub fn working_directory(&self) -> PathBuf {
+        self.root.join(&self.config.working_directory)
+    }
+
+    pub fn output_dir(&self) -> PathBuf {
+        self.root.join(&self.profile.output_dir)
+    }
+
+    /// Where builds for `triple`, or the host, write: `<output_dir>/<triple>` or `<output_dir>/host`.
+    pub fn artifact_dir(&self, triple: Option<&str>) -> PathBuf {
+        self.output_dir().join(triple.unwrap_or(HOST))
+    }
+
+    /// Makes the configured working directory the current one and exports the profile's
+    /// environment, so that compilers and hooks see it.
+    pub fn enter(&self) {
+        let dir = self.working_directory();
+        if let Err(err) = env::set_current_dir(&dir) {
+            error!("Cannot enter working directory {}: {}", dir.display(), err);
+            println!("{}", format!("Working directory {} does not exist.", dir.display()).if_supports_color(Stdout, |text| text.red()));
+            process::exit(5);
+        }
+        info!("Working directory: {}", dir.display());
+
+        env::set_var("CATALYST_PROFILE", &self.profile.name);
+        for (key, value) in &self.profile.env {
+            env::set_var(key, value);
+        }
+    }
+}
+
+/// Walks up from `start` to the nearest `.catalyst/` holding a config file.
+///
+/// When several formats exist in the same directory the first of [`Format::ALL`] wins.
+pub fn discover<P: AsRef<Path>>(start: P) -> Option<PathBuf> {
+    let start = start.as_ref().canonicalize().ok()?;
+    start.ancestors().find_map(find_in)
+}
+
+/// The config file of the project rooted exactly at `dir`, if any.
+pub fn find_in<P: AsRef<Path>>(dir: P) -> Option<PathBuf> {
+    let dir = dir.as_ref().join(CONFIG_DIR);
+    let found = Format::ALL.iter()
+        .map(|format| dir.join(format.file_name()))
+        .filter(|candidate| candidate.is_file())
+        .collect::<Vec<_>>();
+    if found.len() > 1 {
+        warn!("Found {} config files in {}, using {}", found.len(), dir.display(), found[0].display());
+    }
+    found.into_iter().next()
+}
+
+pub fn read_value<P: AsRef<Path>>(path: P, format: Format) -> Result<Value, String> {
+    let content = fs::read_to_string(path).map_err(|err| format!("Cannot read configuration file: {}", err))?;
+    format.parse(&content).map_err(|err| format!("Invalid configuration file: {}", err))
+}
+
+/// Loads the configuration given with `--config`, or the one discovered from the current directory.
+/// Exits the process when none can be loaded.
+pub fn load(matches: &ArgMatches) -> Project {
+    let path = match matches.get_one::<String>("config") {
+        Some(config) => {
+            if Format::from_path(config).is_none() {
+                error!("Not a configuration file.");
+                process::exit(2);
+            }
+            info!("Using configuration file: {}", config.if_supports_color(Stdout, |text| text.purple()).blue());
+            PathBuf::from(config)
+        }
+        None => {
+            info!("Scanning for config files...");
+            match discover(".") {
+                None => {
+                    error!("No config file found.");
+                    println!("{}", "No config file found. Please create a configuration file as i don't know what this directory is...".bold().yellow());
+                    process::exit(1);
+                }
+                Some(path) => {
+                    info!("Found config file: {}", path.display().to_string().if_supports_color(Stdout, |text| text.purple()));
+                    path
+                }
+            }
+        }
+    };
+
+    let profile = matches.get_one::<String>("profile").map(|v| v.as_str()).unwrap_or(profiles::DEFAULT_PROFILE);
+    open(&path, profile)
+}
+
+/// Loads the config file at `path` with the given profile. Exits the process when it cannot.
+pub fn open(path: &Path, name: &str) -> Project {
+    let path = match path.canonicalize() {
+        Ok(path) => path,
+        Err(_) => {
+            error!("Cannot read configuration file.");
+            process::exit(3);
+        }
+    };
+
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(_) => {
+            error!("Cannot read configuration file.");
+            process::exit(3);
+        }
+    };
+
+    let format = Format::from_path(&path).unwrap_or(Format::Json);
+    let root = project_root(&path);
+    info!("Parsing...");
+    let config = match validate::validate(&content, format, &path, &root, name) {
+        Ok(config) => config,
+        Err(problems) => {
+            error!("Invalid configuration file.");
+            validate::report(&path, &problems);
+            process::exit(4);
+        }
+    };
+
+    let profile = match profiles::resolve(&config, name) {
+        Ok(profile) => profile,
+        Err(err) => {
+            error!("{}", err);
+            println!("{}", format!("Cannot use profile {}: {}", name, err).if_supports_color(Stdout, |text| text.red()));
+            process::exit(6);
+        }
+    };
+    info!("Using profile: {}", profile.name);
+
+    Project { root, path, format, config, profile }
+}
+
+/// Writes the project's config file in another format next to the original, which is removed
+/// unless `keep` is set. An existing file in that format is only replaced with `force`.
+pub fn convert(project: &Project, to: Format, keep: bool, force: bool) -> bool {
+    let destination = project.path.with_file_name(to.file_name());
+    if to == project.format {
+        println!("{}", format!("{} is already {}.", project.path.display(), to.extension()).if_supports_color(Stdout, |text| text.yellow()));
+        return true
+    }
+    if destination.exists() && !force {
+        error!("{} already exists", destination.display());
+        println!("{}", format!("{} already exists, use --force to overwrite it.", destination.display()).if_supports_color(Stdout, |text| text.red()));
+        return false
+    }
+
+    let converted = read_value(&project.path, project.format).and_then(|value| to.serialize(&value));
+    let converted = match converted {
+        Ok(converted) => converted,
+        Err(err) => {
+            error!("{}", err);
+            println!("{}", err.if_supports_color(Stdout, |text| text.red()));
+            return false
+        }
+    };
+
+    if let Err(err) = fs::write(&destination, converted) {
+        error!("Cannot write {}: {}", destination.display(), err);
+        return false
+    }
+    if !keep {
+        if let Err(err) = fs::remove_file(&project.path) {
+            error!("Cannot remove {}: {}", project.path.display(), err);
+            return false
+        }
+    }
+    println!("{}", format!("Wrote {}", destination.display()).if_supports_color(Stdout, |text| text.green()));
+    if keep {
+        println!("{}", format!("Kept {}; remove one of the two, only one config file is read.", project.path.display()).if_supports_color(Stdout, |text| text.yellow()));
+    }
+    true
+}
+
+/// The config file as written, or with `resolved` as Catalyst sees it: bases merged in and
+/// variables expanded.
+pub fn value(project: &Project, resolved: bool) -> Result<Value, String> {
+    if !resolved {
+        return read_value(&project.path, project.format)
+    }
+    let content = fs::read_to_string(&project.path).map_err(|err| format!("Cannot read configuration file: {}", err))?;
+    match validate::resolve(&content, project.format, &project.path, &project.root, &project.profile.name) {
+        Ok(mut value) => {
+            if let Some(map) = value.as_object_mut() {
+                map.shift_remove("extends");
+            }
+            Ok(value)
+        }
+        Err(problems) => {
+            validate::report(&project.path, &problems);
+            Err("Invalid configuration file.".to_string())
+        }
+    }
+}
+
+/// Prints the config file, see [`value`].
+pub fn show(project: &Project, resolved: bool) -> bool {
+    if !resolved {
+        match fs::read_to_string(&project.path) {
+            Ok(content) => print!("{}", content),
+            Err(err) => {
+                error!("Cannot read {}: {}", project.path.display(), err);
+                return false
+            }
+        }
+        return true
+    }
+
+    match value(project, true).and_then(|value| project.format.serialize(&value)) {
+        Ok(text) => {
+            println!("{}", text.trim_end());
+            true
+        }
+        Err(err) => {
+            error!("{}", err);
+            println!("{}", err.if_supports_color(Stdout, |text| text.red()));
+            false
+        }
+    }
+}
+
+fn project_root(path: &Path) -> PathBuf {
+    let parent = path.parent().unwrap_or(Path::new("/"));
+    if parent.file_name().is_some_and(|name| name == CONFIG_DIR) {
+        parent.parent().unwrap_or(parent).to_path_buf()
+    } else {
+        parent.to_path_buf()
+    }
+}
@@ -3,7 +3,7 @@ use owo_colors::{OwoColorize, Stream::Stdout};
 use clearscreen::clear;
 use log::{info, warn, error, debug};
 
-use crate::{lua::run_lua, util::{banner, args, find_file}};
+use crate::{hooks::{self, Hook, HookArgs, HookContext}, util::{banner, args, find_file}};
 
 pub fn debug() {
     clear().expect("failed to clear screen");
@@ -15,7 +15,7 @@ pub fn debug() {
         let _ = stdout().flush();
         stdin().read_line(&mut cmd).expect("Failed to read line");
         let words: Vec<&str> = cmd.split_whitespace().collect();
-        print!("\n");
+        println!();
         match words[0] {
             "exit" => {
                 warn!("Exiting");
@@ -71,7 +71,12 @@ pub fn debug() {
                 }
                 else {
                     let path = find_file(".", vec![words[1]]).unwrap();
-                    run_lua(path.display().to_string()).unwrap();
+                    match Hook::load(&path) {
+                        Ok(hook) => {
+                            hooks::run(&hook, &HookContext::default(), &HookArgs::default());
+                        }
+                        Err(err) => println!("{}", err.if_supports_color(Stdout, |text| text.red()).bold())
+                    }
                 }
             }
             "help" => {
@@ -0,0 +1,75 @@
+use std::{fs, io, path::{Path, PathBuf}};
+
+/// Reads the files a compiler reported as dependencies in a Makefile-style depfile, as
+/// written by `-MD -MF`.
+pub fn read(path: &Path) -> io::Result<Vec<PathBuf>> {
+    Ok(parse(&fs::read_to_string(path)?))
+}
+
+/// The prerequisites of every rule in `content`. Handles line continuations, escaped spaces
+/// and `$$`, and tells the rule separator apart from Windows drive letters.
+pub fn parse(content: &str) -> Vec<PathBuf> {
+    let content = content.replace("\\\r\n", " ").replace("\\\n", " ");
+    let mut deps = Vec::new();
+    for line in content.lines() {
+        let Some(separator) = rule_separator(line) else { continue };
+        for dep in split(&line[separator + 1..]) {
+            let dep = PathBuf::from(dep);
+            if !deps.contains(&dep) {
+                deps.push(dep);
+            }
+        }
+    }
+    deps
+}
+
+/// The `:` ending a rule's targets: one followed by whitespace or the end of the line.
+fn rule_separator(line: &str) -> Option<usize> {
+    let bytes = line.as_bytes();
+    (0..bytes.len()).find(|&i| bytes[i] == b':' && bytes.get(i + 1).is_none_or(|next| next.is_ascii_whitespace()))
+}
+
+fn split(list: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut chars = list.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&' ') => word.push(chars.next().unwrap()),
+            '$' if chars.peek() == Some(&'$') => word.push(chars.next().unwrap()),
+            c if c.is_whitespace() => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+            }
+            c => word.push(c)
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn joins_continued_lines() {
+        let deps = parse("out/main.o: src/main.c \\\n  include/a.h \\\r\n  include/b.h\n");
+        assert_eq!(deps, [PathBuf::from("src/main.c"), PathBuf::from("include/a.h"), PathBuf::from("include/b.h")]);
+    }
+
+    #[test]
+    fn unescapes_spaces_and_dollars() {
+        let deps = parse("main.o: my\\ dir/main.c cost$$.h\n");
+        assert_eq!(deps, [PathBuf::from("my dir/main.c"), PathBuf::from("cost$.h")]);
+    }
+
+    #[test]
+    fn keeps_drive_letters_and_drops_repeats() {
+        let deps = parse("C:\\out\\main.o: C:\\src\\main.c\nmain.h:\nmain.o: C:\\src\\main.c main.h\n");
+        assert_eq!(deps, [PathBuf::from("C:\\src\\main.c"), PathBuf::from("main.h")]);
+    }
+}
@@ -0,0 +1,293 @@
+use std::{fs, path::{Path, PathBuf}};
+use log::{info, warn};
+use owo_colors::{OwoColorize, Stream::Stdout};
+use serde::{Deserialize, Serialize};
+
+pub const FILE_NAME: &str = "diagnostics.json";
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Error,
+    Warning,
+    Note
+}
+
+impl Severity {
+    fn from_name(name: &str) -> Option<Severity> {
+        match name {
+            "error" | "fatal error" => Some(Severity::Error),
+            "warning" => Some(Severity::Warning),
+            "note" | "help" | "remark" => Some(Severity::Note),
+            _ => None
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note"
+        }
+    }
+}
+
+/// A message a compiler printed about the code, such as an error or warning.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Diagnostic {
+    pub target: String,
+    pub file: Option<PathBuf>,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+    pub severity: Severity,
+    pub message: String,
+    /// Such as `E0425` from rustc or `-Wunused-variable` from gcc and clang.
+    pub code: Option<String>
+}
+
+impl Diagnostic {
+    fn new(target: &str, severity: Severity, message: &str) -> Diagnostic {
+        let (message, code) = split_code(message);
+        Diagnostic { target: target.to_string(), file: None, line: None, column: None, severity, message, code }
+    }
+
+    fn at(mut self, file: &str, line: u32, column: Option<u32>) -> Diagnostic {
+        self.file = Some(PathBuf::from(file));
+        self.line = Some(line);
+        self.column = column;
+        self
+    }
+
+    fn location(&self) -> String {
+        match (&self.file, self.line, self.column) {
+            (Some(file), Some(line), Some(column)) => format!("{}:{}:{}: ", file.display(), line, column),
+            (Some(file), Some(line), None) => format!("{}:{}: ", file.display(), line),
+            _ => String::new()
+        }
+    }
+}
+
+/// Reads the diagnostics in the output of a compiler: the `file:line:col: severity: message`
+/// lines of gcc, clang, Zig and javac, rustc's `severity[code]: message` followed by
+/// `--> file:line:col`, Go's `file.go:line:col: message`, and location-less lines such as
+/// `collect2: error: ...`.
+pub fn parse(target: &str, output: &str) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    // A rustc header still waiting for its `-->` line.
+    let mut pending: Option<Diagnostic> = None;
+    for line in output.lines() {
+        let trimmed = line.trim_start();
+        if let Some(location) = trimmed.strip_prefix("--> ") {
+            if let Some(diagnostic) = pending.take() {
+                diagnostics.push(match parse_location(&format!("{}:", location.trim_end())) {
+                    Some((file, line, column, _)) => diagnostic.at(file, line, column),
+                    None => diagnostic
+                });
+            }
+            continue;
+        }
+        if let Some(diagnostic) = parse_header(target, line) {
+            diagnostics.extend(pending.take().filter(|diagnostic| !is_summary(&diagnostic.message)));
+            pending = Some(diagnostic);
+            continue;
+        }
+        if line.starts_with(char::is_whitespace) {
+            continue;
+        }
+        if let Some((file, line, column, rest)) = parse_location(line) {
+            diagnostics.extend(pending.take().filter(|diagnostic| !is_summary(&diagnostic.message)));
+            match split_severity(rest) {
+                Some((severity, message)) => diagnostics.push(Diagnostic::new(target, severity, message).at(file, line, column)),
+                None if file.ends_with(".go") => diagnostics.push(Diagnostic::new(target, Severity::Error, rest).at(file, line, column)),
+                None => {}
+            }
+        } else if let Some((tool, rest)) = line.split_once(": ") {
+            if !tool.contains(char::is_whitespace) {
+                if let Some((severity, message)) = split_severity(rest) {
+                    diagnostics.push(Diagnostic::new(target, severity, message));
+                }
+            }
+        }
+    }
+    diagnostics.extend(pending.filter(|diagnostic| !is_summary(&diagnostic.message)));
+    diagnostics
+}
+
+/// rustc's `error[E0425]: message` or `warning: message`, at the start of a line.
+fn parse_header(target: &str, line: &str) -> Option<Diagnostic> {
+    let (head, message) = line.split_once(": ")?;
+    let (name, code) = match head.split_once('[') {
+        Some((name, code)) => (name, Some(code.strip_suffix(']')?)),
+        None => (head, None)
+    };
+    if !["error", "warning", "note"].contains(&name) {
+        return None
+    }
+    let mut diagnostic = Diagnostic::new(target, Severity::from_name(name)?, message);
+    diagnostic.code = code.map(String::from).or(diagnostic.code);
+    Some(diagnostic)
+}
+
+/// Splits `file:line[:col]: rest`, allowing a Windows drive letter in the file.
+fn parse_location(text: &str) -> Option<(&str, u32, Option<u32>, &str)> {
+    let skip = if text.as_bytes().get(1) == Some(&b':') && text.as_bytes().first().is_some_and(u8::is_ascii_alphabetic) { 2 } else { 0 };
+    let colon = skip + text[skip..].find(':')?;
+    let (file, rest) = (&text[..colon], &text[colon + 1..]);
+    if file.is_empty() || file.contains(char::is_whitespace) {
+        return None
+    }
+    let (line, rest) = split_number(rest)?;
+    let rest = rest.strip_prefix(':')?;
+    let (column, rest) = match split_number(rest) {
+        Some((column, rest)) => (Some(column), rest.strip_prefix(':')?),
+        None => (None, rest)
+    };
+    Some((file, line, column, rest.trim_start()))
+}
+
+fn split_number(text: &str) -> Option<(u32, &str)> {
+    let end = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+    Some((text[..end].parse().ok()?, &text[end..]))
+}
+
+/// `severity: message`, as gcc, clang and javac write it after the location.
+fn split_severity(text: &str) -> Option<(Severity, &str)> {
+    let (name, message) = text.split_once(": ")?;
+    Some((Severity::from_name(name)?, message))
+}
+
+/// Takes a trailing `[-Wflag]` off a gcc or clang message.
+fn split_code(message: &str) -> (String, Option<String>) {
+    let message = message.trim();
+    if let Some(start) = message.rfind(" [") {
+        let code = &message[start + 2..];
+        if let Some(code) = code.strip_suffix(']').filter(|code| code.starts_with('-')) {
+            return (message[..start].to_string(), Some(code.to_string()))
+        }
+    }
+    (message.to_string(), None)
+}
+
+/// rustc's closing lines, which count diagnostics rather than report one.
+fn is_summary(message: &str) -> bool {
+    message.starts_with("aborting due to") || message.ends_with("emitted") || message.starts_with("could not compile")
+}
+
+/// Prints the diagnostics grouped by target, each group headed by its counts.
+pub fn summarize(diagnostics: &[Diagnostic]) {
+    let mut targets: Vec<&str> = Vec::new();
+    for diagnostic in diagnostics {
+        if !targets.contains(&diagnostic.target.as_str()) {
+            targets.push(&diagnostic.target);
+        }
+    }
+    for target in targets {
+        let group = diagnostics.iter().filter(|diagnostic| diagnostic.target == target).collect::<Vec<_>>();
+        let count = |severity| group.iter().filter(|diagnostic| diagnostic.severity == severity).count();
+        let (errors, warnings) = (count(Severity::Error), count(Severity::Warning));
+        let heading = format!("{}: {} error{}, {} warning{}", target, errors, plural(errors), warnings, plural(warnings));
+        println!();
+        if errors > 0 {
+            println!("{}", heading.if_supports_color(Stdout, |text| text.red()));
+        } else {
+            println!("{}", heading.if_supports_color(Stdout, |text| text.yellow()));
+        }
+        for diagnostic in group {
+            let code = diagnostic.code.as_ref().map(|code| format!(" [{}]", code)).unwrap_or_default();
+            println!("  {}{}: {}{}", diagnostic.location(), diagnostic.severity.name(), diagnostic.message, code);
+        }
+    }
+}
+
+/// Writes the diagnostics to `diagnostics.json` in `dir`, for editors and CI annotations.
+pub fn write(dir: &Path, diagnostics: &[Diagnostic]) {
+    let path = dir.join(FILE_NAME);
+    let result = fs::create_dir_all(dir).and_then(|_| fs::write(&path, serde_json::to_string_pretty(diagnostics).unwrap() + "\n"));
+    match result {
+        Ok(_) => info!("Wrote {} diagnostics to {}", diagnostics.len(), path.display()),
+        Err(err) => warn!("Cannot write {}: {}", path.display(), err)
+    }
+}
+
+fn plural(count: usize) -> &'static str {
+    if count == 1 { "" } else { "s" }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// File, line, column, severity, message and code.
+    type Summary<'a> = (Option<String>, Option<u32>, Option<u32>, Severity, &'a str, Option<&'a str>);
+
+    fn summary(diagnostic: &Diagnostic) -> Summary<'_> {
+        let file = diagnostic.file.as_ref().map(|file| file.display().to_string());
+        (file, diagnostic.line, diagnostic.column, diagnostic.severity, diagnostic.message.as_str(), diagnostic.code.as_deref())
+    }
+
+    #[test]
+    fn reads_gcc_and_clang() {
+        let output = "src/main.c: In function 'main':\n\
+            src/main.c:3:9: warning: unused variable 'x' [-Wunused-variable]\n    \
+                3 |     int x;\n      \
+                  |         ^\n\
+            src/main.c:4:20: error: 'missing' undeclared (first use in this function)\n\
+            collect2: error: ld returned 1 exit status\n";
+        let diagnostics = parse("app", output);
+        assert_eq!(diagnostics.len(), 3);
+        assert_eq!(summary(&diagnostics[0]), (Some("src/main.c".to_string()), Some(3), Some(9), Severity::Warning, "unused variable 'x'", Some("-Wunused-variable")));
+        assert_eq!(summary(&diagnostics[1]), (Some("src/main.c".to_string()), Some(4), Some(20), Severity::Error, "'missing' undeclared (first use in this function)", None));
+        assert_eq!(summary(&diagnostics[2]), (None, None, None, Severity::Error, "ld returned 1 exit status", None));
+        assert_eq!(diagnostics[0].target, "app");
+    }
+
+    #[test]
+    fn reads_rustc() {
+        let output = "error[E0425]: cannot find value `y` in this scope\n \
+            --> src/main.rs:2:13\n  \
+              |\n\
+            2 |     let x = y;\n  \
+              |             ^ not found in this scope\n\
+            \n\
+            warning: unused variable: `x`\n \
+            --> src/main.rs:2:9\n  \
+              |\n  \
+              = help: if this is intentional, prefix it with an underscore: `_x`\n\
+            \n\
+            error: aborting due to 1 previous error; 1 warning emitted\n";
+        let diagnostics = parse("app", output);
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(summary(&diagnostics[0]), (Some("src/main.rs".to_string()), Some(2), Some(13), Severity::Error, "cannot find value `y` in this scope", Some("E0425")));
+        assert_eq!(summary(&diagnostics[1]), (Some("src/main.rs".to_string()), Some(2), Some(9), Severity::Warning, "unused variable: `x`", None));
+    }
+
+    #[test]
+    fn reads_go() {
+        let diagnostics = parse("app", "# app\n./main.go:5:2: declared and not used: x\n");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(summary(&diagnostics[0]), (Some("./main.go".to_string()), Some(5), Some(2), Severity::Error, "declared and not used: x", None));
+    }
+
+    #[test]
+    fn reads_javac() {
+        let output = "src/Main.java:3: error: cannot find symbol\n        missing();\n        ^\n  symbol:   method missing()\n1 error\n";
+        let diagnostics = parse("app", output);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(summary(&diagnostics[0]), (Some("src/Main.java".to_string()), Some(3), None, Severity::Error, "cannot find symbol", None));
+    }
+
+    #[test]
+    fn reads_zig() {
+        let output = "src/main.zig:2:5: error: use of undeclared identifier 'y'\n    y = 1;\n    ^\nsrc/main.zig:1:1: note: called from here\n";
+        let diagnostics = parse("app", output);
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(summary(&diagnostics[0]), (Some("src/main.zig".to_string()), Some(2), Some(5), Severity::Error, "use of undeclared identifier 'y'", None));
+        assert_eq!(diagnostics[1].severity, Severity::Note);
+    }
+
+    #[test]
+    fn keeps_windows_drive_letters() {
+        let diagnostics = parse("app", "C:\\src\\main.c:7:1: warning: no newline at end of file\n");
+        assert_eq!(summary(&diagnostics[0]), (Some("C:\\src\\main.c".to_string()), Some(7), Some(1), Severity::Warning, "no newline at end of file", None));
+    }
+}
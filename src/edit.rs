@@ -0,0 +1,373 @@
+use std::{fs, mem};
+use log::error;
+use owo_colors::{OwoColorize, Stream::Stdout};
+use serde_json::{Map, Value};
+use toml_edit::{Array, DocumentMut, InlineTable, Item, Table};
+
+use crate::{config::{self, Format, Project}, validate::{self, KeyPath, Segment}};
+
+/// Prints the value at `key`: strings as they are, anything else as JSON.
+pub fn get(project: &Project, key: &str, resolved: bool) -> bool {
+    let found = config::value(project, resolved).and_then(|value| {
+        let path = parse_key(&value, key)?;
+        lookup(&value, &path).cloned().ok_or(format!("`{}` is not set", key))
+    });
+    match found {
+        Ok(Value::String(text)) => println!("{}", text),
+        Ok(value) => println!("{}", serde_json::to_string_pretty(&value).unwrap()),
+        Err(err) => return fail(&err)
+    }
+    true
+}
+
+/// Prints every value of the config as `key = json`, one per line.
+pub fn list(project: &Project, resolved: bool) -> bool {
+    let value = match config::value(project, resolved) {
+        Ok(value) => value,
+        Err(err) => return fail(&err)
+    };
+    let mut entries = Vec::new();
+    leaves(&value, &mut Vec::new(), &mut entries);
+    for (path, value) in entries {
+        println!("{} = {}", KeyPath(&path), value);
+    }
+    true
+}
+
+/// Sets `key` in the config file, creating the tables on the way. The value is taken as a
+/// string unless the schema wants something else there, in which case it is read as JSON.
+pub fn set(project: &Project, key: &str, raw: &str) -> bool {
+    let mut candidates = vec![Value::String(raw.to_string())];
+    if let Ok(parsed) = serde_json::from_str::<Value>(raw) {
+        if !parsed.is_string() {
+            candidates.push(parsed);
+        }
+    }
+    write(project, key, &candidates)
+}
+
+pub fn unset(project: &Project, key: &str) -> bool {
+    write(project, key, &[])
+}
+
+/// Applies the first candidate that leaves the file valid, or removes `key` without any.
+/// The file is only written when the result passes validation.
+fn write(project: &Project, key: &str, candidates: &[Value]) -> bool {
+    let content = match fs::read_to_string(&project.path) {
+        Ok(content) => content,
+        Err(err) => return fail(&format!("Cannot read {}: {}", project.path.display(), err))
+    };
+    let value = match project.format.parse(&content) {
+        Ok(value) => value,
+        Err(err) => return fail(&err)
+    };
+    let path = match parse_key(&value, key) {
+        Ok(path) => path,
+        Err(err) => return fail(&err)
+    };
+
+    let edits = if candidates.is_empty() {
+        if lookup(&value, &path).is_none() {
+            return fail(&format!("`{}` is not set", key))
+        }
+        vec![edit(&content, project.format, &value, &path, None)]
+    } else {
+        candidates.iter().map(|candidate| edit(&content, project.format, &value, &path, Some(candidate))).collect()
+    };
+
+    let mut rejected = None;
+    for edited in edits {
+        let edited = match edited {
+            Ok(edited) => edited,
+            Err(err) => return fail(&err)
+        };
+        match validate::validate(&edited, project.format, &project.path, &project.root, &project.profile.name) {
+            Ok(_) => {
+                if let Err(err) = fs::write(&project.path, edited) {
+                    return fail(&format!("Cannot write {}: {}", project.path.display(), err))
+                }
+                println!("{}", format!("Updated {}", project.path.display()).if_supports_color(Stdout, |text| text.green()));
+                return true
+            }
+            Err(problems) => rejected = Some(problems)
+        }
+    }
+
+    validate::report(&project.path, &rejected.unwrap_or_default());
+    fail(&format!("{} was left unchanged.", project.path.display()))
+}
+
+fn fail(message: &str) -> bool {
+    error!("{}", message);
+    println!("{}", message.if_supports_color(Stdout, |text| text.red()));
+    false
+}
+
+/// Turns `targets.app.flags` or `targets[0].flags` into a path into `value`. A name used
+/// on a list picks the entry with that `name`.
+pub fn parse_key(value: &Value, key: &str) -> Result<Vec<Segment>, String> {
+    let tokens = key.replace('[', ".").replace(']', "");
+    let mut path = Vec::new();
+    let mut current = Some(value);
+    for token in tokens.split('.') {
+        if token.is_empty() {
+            return Err(format!("invalid key `{}`", key))
+        }
+        let segment = match current {
+            Some(Value::Array(items)) => match token.parse::<usize>() {
+                Ok(index) => Segment::Index(index),
+                Err(_) => items.iter()
+                    .position(|item| item.get("name").and_then(Value::as_str) == Some(token))
+                    .map(Segment::Index)
+                    .ok_or(format!("no entry named `{}` in `{}`", token, KeyPath(&path)))?
+            },
+            _ => Segment::Key(token.to_string())
+        };
+        current = current.and_then(|value| child(value, &segment));
+        path.push(segment);
+    }
+    Ok(path)
+}
+
+fn child<'a>(value: &'a Value, segment: &Segment) -> Option<&'a Value> {
+    match segment {
+        Segment::Key(key) => value.get(key.as_str()),
+        Segment::Index(index) => value.get(*index)
+    }
+}
+
+fn lookup<'a>(value: &'a Value, path: &[Segment]) -> Option<&'a Value> {
+    path.iter().try_fold(value, child)
+}
+
+fn leaves(value: &Value, path: &mut Vec<Segment>, entries: &mut Vec<(Vec<Segment>, Value)>) {
+    match value {
+        Value::Object(map) if !map.is_empty() => for (key, value) in map {
+            path.push(Segment::Key(key.clone()));
+            leaves(value, path, entries);
+            path.pop();
+        },
+        Value::Array(items) if !items.is_empty() => for (i, value) in items.iter().enumerate() {
+            path.push(Segment::Index(i));
+            leaves(value, path, entries);
+            path.pop();
+        },
+        value => entries.push((path.clone(), value.clone()))
+    }
+}
+
+/// The file's content with `path` set to `new`, or removed when it is `None`. TOML is edited
+/// in place to keep its comments and layout; JSON and YAML are written back in key order.
+fn edit(content: &str, format: Format, value: &Value, path: &[Segment], new: Option<&Value>) -> Result<String, String> {
+    let mut edited = match format {
+        Format::Toml => {
+            let mut document = content.parse::<DocumentMut>().map_err(|err| err.to_string())?;
+            edit_toml(document.as_item_mut(), path, path, new)?;
+            document.to_string()
+        }
+        Format::Json | Format::Yaml => {
+            let mut value = value.clone();
+            match new {
+                Some(new) => set_value(&mut value, path, new.clone())?,
+                None => remove_value(&mut value, path)
+            }
+            format.serialize(&value)?
+        }
+    };
+    if content.ends_with('\n') && !edited.ends_with('\n') {
+        edited.push('\n');
+    }
+    Ok(edited)
+}
+
+fn set_value(value: &mut Value, path: &[Segment], new: Value) -> Result<(), String> {
+    let mut current = value;
+    for (i, segment) in path.iter().enumerate() {
+        if current.is_null() {
+            *current = Value::Object(Map::new());
+        }
+        let last = i + 1 == path.len();
+        current = match (segment, current) {
+            (Segment::Key(key), Value::Object(map)) if last => {
+                map.insert(key.clone(), new);
+                return Ok(())
+            }
+            (Segment::Key(key), Value::Object(map)) => map.entry(key.clone()).or_insert(Value::Null),
+            (Segment::Index(index), Value::Array(items)) if last && *index == items.len() => {
+                items.push(new);
+                return Ok(())
+            }
+            (Segment::Index(index), Value::Array(items)) if *index < items.len() => &mut items[*index],
+            (Segment::Index(index), Value::Array(_)) => return Err(format!("`{}` has no entry {}", KeyPath(&path[..i]), index)),
+            _ => return Err(format!("`{}` is not a table", KeyPath(&path[..i])))
+        };
+        if last {
+            *current = new;
+            return Ok(())
+        }
+    }
+    Ok(())
+}
+
+fn remove_value(value: &mut Value, path: &[Segment]) {
+    let Some((last, parents)) = path.split_last() else { return };
+    let mut current = value;
+    for segment in parents {
+        current = match segment {
+            Segment::Key(key) => match current.get_mut(key.as_str()) {
+                Some(next) => next,
+                None => return
+            },
+            Segment::Index(index) => match current.get_mut(*index) {
+                Some(next) => next,
+                None => return
+            }
+        };
+    }
+    match (last, current) {
+        (Segment::Key(key), Value::Object(map)) => { map.shift_remove(key); }
+        (Segment::Index(index), Value::Array(items)) if *index < items.len() => { items.remove(*index); }
+        _ => {}
+    }
+}
+
+fn edit_toml(item: &mut Item, path: &[Segment], full: &[Segment], new: Option<&Value>) -> Result<(), String> {
+    let Some((segment, rest)) = path.split_first() else { return Ok(()) };
+    let here = KeyPath(&full[..full.len() - path.len()]).to_string();
+    match segment {
+        Segment::Key(key) => {
+            let inline = !item.is_table();
+            let table = item.as_table_like_mut().ok_or(format!("`{}` is not a table", here))?;
+            if rest.is_empty() {
+                match new {
+                    Some(new) => {
+                        let value = toml_value(new)?;
+                        match table.get_mut(key) {
+                            Some(Item::Value(old)) => {
+                                let decor = old.decor().clone();
+                                *old = value;
+                                *old.decor_mut() = decor;
+                            }
+                            _ => { table.insert(key, Item::Value(value)); }
+                        }
+                    }
+                    None => { table.remove(key); }
+                }
+                return Ok(())
+            }
+            if !table.contains_key(key) {
+                let empty = if inline {
+                    Item::Value(InlineTable::new().into())
+                } else {
+                    let mut table = Table::new();
+                    table.set_implicit(true);
+                    Item::Table(table)
+                };
+                table.insert(key, empty);
+            }
+            edit_toml(table.get_mut(key).unwrap(), rest, full, new)
+        }
+        Segment::Index(index) => match item {
+            Item::ArrayOfTables(tables) => {
+                if rest.is_empty() {
+                    match new {
+                        Some(new) => {
+                            let table = match toml_value(new)? {
+                                toml_edit::Value::InlineTable(table) => table.into_table(),
+                                _ => return Err(format!("entries of `{}` are tables", here))
+                            };
+                            if *index < tables.len() {
+                                *tables.get_mut(*index).unwrap() = table;
+                            } else if *index == tables.len() {
+                                tables.push(table);
+                            } else {
+                                return Err(format!("`{}` has no entry {}", here, index))
+                            }
+                        }
+                        None => tables.remove(*index)
+                    }
+                    return Ok(())
+                }
+                let table = tables.get_mut(*index).ok_or(format!("`{}` has no entry {}", here, index))?;
+                let mut entry = Item::Table(mem::take(table));
+                let result = edit_toml(&mut entry, rest, full, new);
+                *table = entry.into_table().unwrap_or_default();
+                result
+            }
+            Item::Value(toml_edit::Value::Array(array)) => {
+                if rest.is_empty() {
+                    match new {
+                        Some(new) if *index < array.len() => { array.replace(*index, toml_value(new)?); }
+                        Some(new) if *index == array.len() => array.push(toml_value(new)?),
+                        Some(_) => return Err(format!("`{}` has no entry {}", here, index)),
+                        None => { array.remove(*index); }
+                    }
+                    return Ok(())
+                }
+                let slot = array.get_mut(*index).ok_or(format!("`{}` has no entry {}", here, index))?;
+                let mut entry = Item::Value(mem::replace(slot, false.into()));
+                let result = edit_toml(&mut entry, rest, full, new);
+                if let Ok(value) = entry.into_value() {
+                    *slot = value;
+                }
+                result
+            }
+            _ => Err(format!("`{}` is not a list", here))
+        }
+    }
+}
+
+fn toml_value(value: &Value) -> Result<toml_edit::Value, String> {
+    Ok(match value {
+        Value::Null => return Err("TOML has no null, use `cly config unset` instead".to_string()),
+        Value::Bool(value) => (*value).into(),
+        Value::Number(number) => match number.as_i64() {
+            Some(number) => number.into(),
+            None => number.as_f64().unwrap_or_default().into()
+        },
+        Value::String(text) => text.as_str().into(),
+        Value::Array(items) => items.iter().map(toml_value).collect::<Result<Array, String>>()?.into(),
+        Value::Object(map) => {
+            let mut table = InlineTable::new();
+            for (key, value) in map {
+                table.insert(key, toml_value(value)?);
+            }
+            table.into()
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn key(name: &str) -> Segment {
+        Segment::Key(name.to_string())
+    }
+
+    #[test]
+    fn reads_dotted_and_indexed_keys() {
+        let value = json!({ "targets": [{ "name": "app", "flags": ["-O2"] }] });
+        assert_eq!(parse_key(&value, "targets[0].flags[0]"), Ok(vec![key("targets"), Segment::Index(0), key("flags"), Segment::Index(0)]));
+        assert_eq!(parse_key(&value, "targets.0.flags"), Ok(vec![key("targets"), Segment::Index(0), key("flags")]));
+    }
+
+    #[test]
+    fn finds_list_entries_by_name() {
+        let value = json!({ "targets": [{ "name": "lib" }, { "name": "app" }] });
+        assert_eq!(parse_key(&value, "targets.app.kind"), Ok(vec![key("targets"), Segment::Index(1), key("kind")]));
+        assert_eq!(parse_key(&value, "targets.tool"), Err("no entry named `tool` in `targets`".to_string()));
+    }
+
+    #[test]
+    fn allows_keys_that_do_not_exist_yet() {
+        assert_eq!(parse_key(&json!({}), "profiles.ci.optimize"), Ok(vec![key("profiles"), key("ci"), key("optimize")]));
+    }
+
+    #[test]
+    fn rejects_empty_parts() {
+        assert_eq!(parse_key(&json!({}), "targets..flags"), Err("invalid key `targets..flags`".to_string()));
+        assert_eq!(parse_key(&json!({}), ""), Err("invalid key ``".to_string()));
+    }
+}
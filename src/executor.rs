@@ -0,0 +1,146 @@
+use std::{collections::BTreeSet, panic::{self, AssertUnwindSafe}, sync::{Condvar, Mutex}, thread};
+use clap::ArgMatches;
+use owo_colors::{OwoColorize, Stream::Stdout};
+
+use crate::util;
+
+/// How many tasks run at once, and whether a failure stops the others from starting.
+#[derive(Clone, Copy)]
+pub struct Options {
+    pub jobs: usize,
+    pub keep_going: bool
+}
+
+impl Options {
+    pub fn from_matches(matches: &ArgMatches) -> Options {
+        Options {
+            jobs: matches.get_one::<usize>("jobs").copied().unwrap_or_else(util::cpu_count).max(1),
+            keep_going: matches.get_flag("keep_going")
+        }
+    }
+}
+
+type Run<'a> = Box<dyn FnOnce() -> Result<(), String> + Send + 'a>;
+
+/// A unit of work that starts once every task in `deps` succeeded.
+pub struct Task<'a> {
+    pub label: String,
+    /// Indices of earlier tasks.
+    pub deps: Vec<usize>,
+    run: Run<'a>
+}
+
+impl<'a> Task<'a> {
+    pub fn new<F: FnOnce() -> Result<(), String> + Send + 'a>(label: String, deps: Vec<usize>, run: F) -> Task<'a> {
+        Task { label, deps, run: Box::new(run) }
+    }
+}
+
+#[derive(Default)]
+pub struct Outcome {
+    /// Label and error of each task that failed.
+    pub failed: Vec<(String, String)>,
+    /// Labels of the tasks that never started because of a failure.
+    pub skipped: Vec<String>
+}
+
+impl Outcome {
+    pub fn success(&self) -> bool {
+        self.failed.is_empty() && self.skipped.is_empty()
+    }
+
+    /// Prints what did not run because of a failure, if anything.
+    pub fn summarize(&self) {
+        if !self.skipped.is_empty() {
+            let mut labels = self.skipped.clone();
+            labels.sort_unstable();
+            labels.dedup();
+            println!("{}", format!("Skipped after failures: {}", labels.join(", ")).if_supports_color(Stdout, |text| text.yellow()));
+        }
+    }
+}
+
+struct State<'a> {
+    runs: Vec<Option<Run<'a>>>,
+    /// Unfinished dependencies of each task.
+    waiting: Vec<usize>,
+    /// Started lowest first, so that targets finish in the order they are declared.
+    ready: BTreeSet<usize>,
+    running: usize,
+    stopped: bool,
+    failed: Vec<(String, String)>
+}
+
+/// Runs `tasks` on up to `options.jobs` threads, each as soon as its dependencies succeeded.
+///
+/// A failed task stops new tasks from starting and lets the running ones finish, unless
+/// `keep_going` is set, in which case only the tasks depending on it are skipped. A task
+/// that panics counts as failed.
+pub fn execute(tasks: Vec<Task>, options: Options) -> Outcome {
+    let labels = tasks.iter().map(|task| task.label.clone()).collect::<Vec<_>>();
+    let mut dependents = vec![Vec::new(); tasks.len()];
+    for (i, task) in tasks.iter().enumerate() {
+        for &dep in &task.deps {
+            dependents[dep].push(i);
+        }
+    }
+
+    let state = Mutex::new(State {
+        waiting: tasks.iter().map(|task| task.deps.len()).collect(),
+        ready: tasks.iter().enumerate().filter(|(_, task)| task.deps.is_empty()).map(|(i, _)| i).collect(),
+        runs: tasks.into_iter().map(|task| Some(task.run)).collect(),
+        running: 0,
+        stopped: false,
+        failed: Vec::new()
+    });
+    let wake = Condvar::new();
+
+    let workers = options.jobs.min(labels.len()).max(1);
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let (index, run) = {
+                    let mut state = state.lock().unwrap();
+                    loop {
+                        if !state.stopped {
+                            if let Some(index) = state.ready.pop_first() {
+                                state.running += 1;
+                                let run = state.runs[index].take().unwrap();
+                                break (index, run)
+                            }
+                        }
+                        if state.running == 0 && (state.stopped || state.ready.is_empty()) {
+                            wake.notify_all();
+                            return
+                        }
+                        state = wake.wait(state).unwrap();
+                    }
+                };
+
+                let result = panic::catch_unwind(AssertUnwindSafe(run)).unwrap_or(Err("panicked".to_string()));
+
+                let mut state = state.lock().unwrap();
+                state.running -= 1;
+                match result {
+                    Ok(()) => for &dependent in &dependents[index] {
+                        state.waiting[dependent] -= 1;
+                        if state.waiting[dependent] == 0 {
+                            state.ready.insert(dependent);
+                        }
+                    },
+                    Err(err) => {
+                        state.failed.push((labels[index].clone(), err));
+                        state.stopped |= !options.keep_going;
+                    }
+                }
+                wake.notify_all();
+            });
+        }
+    });
+
+    let state = state.into_inner().unwrap();
+    Outcome {
+        skipped: state.runs.iter().enumerate().filter(|(_, run)| run.is_some()).map(|(i, _)| labels[i].clone()).collect(),
+        failed: state.failed
+    }
+}
@@ -0,0 +1,213 @@
+use std::{fmt, fs, path::{Path, PathBuf}};
+use git2::{build::RepoBuilder, Object, Repository};
+use log::{info, warn};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+
+use crate::{cache, config::Format, util, validate::{Problem, Segment}};
+
+/// Where a base config comes from.
+enum Source {
+    File(PathBuf),
+    /// `git+<url>#<ref>:<path>`, with an empty ref meaning the default branch.
+    Git { url: String, reference: String, path: PathBuf }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::File(path) => write!(f, "{}", path.display()),
+            Source::Git { url, reference, path } => write!(f, "git+{}#{}:{}", url, reference, path.display())
+        }
+    }
+}
+
+impl Source {
+    /// Resolves an `extends` entry of the config found at `self`. Relative paths in a git
+    /// base stay in the same repository and ref. Paths are normalized, so that the same base
+    /// reached through different paths is recognised in cycles.
+    fn join(&self, entry: &str) -> Result<Source, String> {
+        if let Some(spec) = entry.strip_prefix("git+") {
+            let (url, fragment) = spec.split_once('#').ok_or(format!("`{}` has no `#<ref>:<path>`", entry))?;
+            let (reference, path) = fragment.split_once(':').ok_or(format!("`{}` has no `:<path>` after the ref", entry))?;
+            return Ok(Source::Git { url: url.to_string(), reference: reference.to_string(), path: util::normalize(Path::new(path)) })
+        }
+        Ok(match self {
+            Source::File(path) => Source::File(util::normalize(&path.parent().unwrap_or(Path::new(".")).join(entry))),
+            Source::Git { url, reference, path } => Source::Git {
+                url: url.clone(),
+                reference: reference.clone(),
+                path: util::normalize(&path.parent().unwrap_or(Path::new("")).join(entry))
+            }
+        })
+    }
+
+    fn read(&self) -> Result<Value, String> {
+        let path = match self {
+            Source::File(path) | Source::Git { path, .. } => path
+        };
+        let format = Format::from_path(path).ok_or(format!("{} is not a configuration file", self))?;
+        let content = match self {
+            Source::File(path) => fs::read_to_string(path).map_err(|err| format!("cannot read {}: {}", self, err))?,
+            Source::Git { url, reference, path } => read_blob(url, reference, path)?
+        };
+        format.parse(&content).map_err(|err| format!("invalid base config {}: {}", self, err))
+    }
+}
+
+/// Merges the bases listed in `extends` under a parsed config, depth first and in order,
+/// so that later bases and then the config itself win. `file` is the config's own path.
+pub fn resolve(value: Value, file: &Path) -> Result<Value, Vec<Problem>> {
+    let source = Source::File(file.to_path_buf());
+    let mut chain = vec![source.to_string()];
+    let mut merged = Value::Object(Map::new());
+    for (i, entry) in entries(&value).into_iter().enumerate() {
+        let base = source.join(&entry).and_then(|base| load(base, &mut chain));
+        match base {
+            Ok(base) => merged = merge(merged, base),
+            Err(message) => return Err(vec![Problem::new(&[Segment::Key("extends".to_string()), Segment::Index(i)], message, None)])
+        }
+    }
+    Ok(merge(merged, value))
+}
+
+fn load(source: Source, chain: &mut Vec<String>) -> Result<Value, String> {
+    let name = source.to_string();
+    if chain.contains(&name) {
+        chain.push(name);
+        return Err(format!("extends cycle: {}", chain.join(" -> ")))
+    }
+    let mut value = source.read()?;
+    chain.push(name);
+    let mut merged = Value::Object(Map::new());
+    for entry in entries(&value) {
+        merged = merge(merged, load(source.join(&entry)?, chain)?);
+    }
+    chain.pop();
+
+    // Only the extends list of the config being loaded is kept.
+    if let Some(map) = value.as_object_mut() {
+        map.shift_remove("extends");
+    }
+    Ok(merge(merged, value))
+}
+
+/// The string entries of `extends`. Anything else is left for the schema check to report.
+fn entries(value: &Value) -> Vec<String> {
+    value.get("extends").and_then(Value::as_array).into_iter().flatten()
+        .filter_map(|entry| entry.as_str().map(String::from))
+        .collect()
+}
+
+/// Layers `child` over `base`.
+///
+/// Tables merge key by key. Lists are concatenated, base entries first, except that a table
+/// with a `name` merges into the base entry of the same name and that `hooks` lists keep
+/// each hook once. Anything else in `child` replaces what is in `base`.
+pub fn merge(base: Value, child: Value) -> Value {
+    merge_at(base, child, "")
+}
+
+fn merge_at(base: Value, child: Value, key: &str) -> Value {
+    match (base, child) {
+        (Value::Object(mut base), Value::Object(child)) => {
+            for (key, value) in child {
+                match base.get_mut(&key) {
+                    Some(slot) => *slot = merge_at(slot.take(), value, &key),
+                    None => { base.insert(key, value); }
+                }
+            }
+            Value::Object(base)
+        }
+        (Value::Array(mut base), Value::Array(child)) => {
+            for item in child {
+                let name = item.get("name").filter(|name| name.is_string()).cloned();
+                let existing = name.and_then(|name| base.iter().position(|entry| entry.get("name") == Some(&name)));
+                match existing {
+                    Some(i) => base[i] = merge_at(base[i].take(), item, ""),
+                    None if key == "hooks" && base.contains(&item) => {}
+                    None => base.push(item)
+                }
+            }
+            Value::Array(base)
+        }
+        (_, child) => child
+    }
+}
+
+fn read_blob(url: &str, reference: &str, path: &Path) -> Result<String, String> {
+    let repo = fetch(url)?;
+    let commit = find_ref(&repo, reference).ok_or(format!("no ref `{}` in {}", reference, url))?;
+    let tree = commit.peel_to_tree().map_err(|err| err.message().to_string())?;
+    let entry = tree.get_path(path).map_err(|_| format!("no {} at `{}` in {}", path.display(), reference, url))?;
+    let blob = repo.find_blob(entry.id()).map_err(|err| err.message().to_string())?;
+    String::from_utf8(blob.content().to_vec()).map_err(|_| format!("{} is not UTF-8", path.display()))
+}
+
+/// Clones `url` into the cache on first use, and updates the cached copy afterwards.
+/// A cached copy that cannot be updated is still used, so builds work offline.
+fn fetch(url: &str) -> Result<Repository, String> {
+    let digest = cache::hex(&Sha256::digest(url.as_bytes()));
+    let dir = util::cache_dir().join("extends").join(&digest[..16]);
+    if dir.exists() {
+        let repo = Repository::open_bare(&dir).map_err(|err| format!("cannot open cached {}: {}", url, err.message()))?;
+        let updated = repo.find_remote("origin").and_then(|mut remote| remote.fetch::<&str>(&[], None, None));
+        if let Err(err) = updated {
+            warn!("Cannot update {}, using the cached copy: {}", url, err.message());
+        }
+        return Ok(repo)
+    }
+
+    info!("Fetching {}", url);
+    if let Some(parent) = dir.parent() {
+        fs::create_dir_all(parent).map_err(|err| format!("cannot create {}: {}", parent.display(), err))?;
+    }
+    RepoBuilder::new().bare(true).clone(url, &dir).map_err(|err| format!("cannot fetch {}: {}", url, err.message()))
+}
+
+fn find_ref<'a>(repo: &'a Repository, reference: &str) -> Option<Object<'a>> {
+    let candidates = if reference.is_empty() {
+        vec!["refs/remotes/origin/HEAD".to_string(), "HEAD".to_string()]
+    } else {
+        vec![
+            format!("refs/remotes/origin/{}", reference),
+            format!("refs/tags/{}", reference),
+            reference.to_string()
+        ]
+    };
+    candidates.iter().find_map(|candidate| repo.revparse_single(candidate).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn merges_tables_key_by_key() {
+        let merged = merge(json!({ "name": "base", "vars": { "a": "1", "b": "2" } }), json!({ "vars": { "b": "3" }, "version": "1.0" }));
+        assert_eq!(merged, json!({ "name": "base", "vars": { "a": "1", "b": "3" }, "version": "1.0" }));
+    }
+
+    #[test]
+    fn concatenates_lists_and_merges_named_entries() {
+        let base = json!({ "targets": [{ "name": "app", "sources": ["a.c"], "kind": "executable" }, { "name": "lib" }] });
+        let child = json!({ "targets": [{ "name": "app", "sources": ["b.c"] }, { "name": "tool" }] });
+        assert_eq!(merge(base, child), json!({ "targets": [
+            { "name": "app", "sources": ["a.c", "b.c"], "kind": "executable" },
+            { "name": "lib" },
+            { "name": "tool" }
+        ] }));
+    }
+
+    #[test]
+    fn keeps_each_hook_once() {
+        let merged = merge(json!({ "hooks": ["fmt", "gen"], "matrix": ["x"] }), json!({ "hooks": ["gen", "lint"], "matrix": ["x"] }));
+        assert_eq!(merged, json!({ "hooks": ["fmt", "gen", "lint"], "matrix": ["x", "x"] }));
+    }
+
+    #[test]
+    fn replaces_anything_else() {
+        assert_eq!(merge(json!({ "version": "1.0", "vars": { "a": "1" } }), json!({ "version": null, "vars": "none" })), json!({ "version": null, "vars": "none" }));
+    }
+}
@@ -0,0 +1,91 @@
+/// Orders the nodes of a dependency graph so that every node comes after the nodes it
+/// depends on. `deps[i]` lists the dependencies of node `i`; nodes that do not depend on
+/// each other keep their input order.
+///
+/// On a cycle, returns the nodes along it, starting and ending with the same node.
+pub fn order(deps: &[Vec<usize>]) -> Result<Vec<usize>, Vec<usize>> {
+    let mut state = vec![Visit::New; deps.len()];
+    let mut ordered = Vec::with_capacity(deps.len());
+    let mut stack = Vec::new();
+    for node in 0..deps.len() {
+        visit(node, deps, &mut state, &mut stack, &mut ordered)?;
+    }
+    Ok(ordered)
+}
+
+/// Every node reachable from `roots`, including the roots themselves.
+pub fn closure(deps: &[Vec<usize>], roots: &[usize]) -> Vec<bool> {
+    let mut reached = vec![false; deps.len()];
+    let mut pending = roots.to_vec();
+    while let Some(node) = pending.pop() {
+        if !reached[node] {
+            reached[node] = true;
+            pending.extend(&deps[node]);
+        }
+    }
+    reached
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Visit {
+    New,
+    Active,
+    Done
+}
+
+fn visit(node: usize, deps: &[Vec<usize>], state: &mut [Visit], stack: &mut Vec<usize>, ordered: &mut Vec<usize>) -> Result<(), Vec<usize>> {
+    match state[node] {
+        Visit::Done => return Ok(()),
+        Visit::Active => {
+            let start = stack.iter().position(|n| *n == node).unwrap_or(0);
+            let mut cycle = stack[start..].to_vec();
+            cycle.push(node);
+            return Err(cycle)
+        }
+        Visit::New => {}
+    }
+    state[node] = Visit::Active;
+    stack.push(node);
+    for dep in &deps[node] {
+        visit(*dep, deps, state, stack, ordered)?;
+    }
+    stack.pop();
+    state[node] = Visit::Done;
+    ordered.push(node);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn puts_dependencies_first_and_keeps_input_order() {
+        let deps = vec![vec![2], vec![], vec![1], vec![]];
+        assert_eq!(order(&deps), Ok(vec![1, 2, 0, 3]));
+    }
+
+    #[test]
+    fn reports_the_cycle_path() {
+        let deps = vec![vec![1], vec![2], vec![0], vec![]];
+        assert_eq!(order(&deps), Err(vec![0, 1, 2, 0]));
+    }
+
+    #[test]
+    fn reports_a_cycle_away_from_the_first_node() {
+        let deps = vec![vec![1], vec![2], vec![1]];
+        assert_eq!(order(&deps), Err(vec![1, 2, 1]));
+    }
+
+    #[test]
+    fn reports_a_node_depending_on_itself() {
+        assert_eq!(order(&[vec![0]]), Err(vec![0, 0]));
+    }
+
+    #[test]
+    fn closure_follows_dependencies_only() {
+        let deps = vec![vec![1], vec![], vec![0], vec![]];
+        assert_eq!(closure(&deps, &[0]), vec![true, true, false, false]);
+        assert_eq!(closure(&deps, &[2, 3]), vec![true, true, true, true]);
+    }
+}
@@ -0,0 +1,211 @@
+use std::{collections::HashMap, env::{self, consts}, fs, io, path::{Path, PathBuf}, process::Command, sync::atomic::{AtomicUsize, Ordering}};
+use log::{info, warn};
+
+use crate::{compiler::{which, Action}, util};
+
+/// `PATH` of hermetic actions, after the compiler's own directory.
+const UNIX_PATH: &str = "/usr/local/bin:/usr/bin:/bin";
+/// Variables Windows programs cannot start without.
+const WINDOWS_ENV: [&str; 4] = ["SYSTEMROOT", "WINDIR", "TEMP", "TMP"];
+
+/// Numbers sandboxes and traces, so that parallel actions do not share them.
+static RUNS: AtomicUsize = AtomicUsize::new(0);
+
+/// An action prepared to run hermetically: with a scrubbed environment, and either traced
+/// with strace to learn the files it reads, or, where strace is missing, from a sandbox
+/// directory holding links to the declared files only.
+pub struct Hermetic {
+    action: Action,
+    /// Variables kept from the environment on top of the fixed ones.
+    keep: Vec<String>,
+    strace: Option<PathBuf>,
+    trace: PathBuf,
+    sandbox: Option<PathBuf>
+}
+
+impl Hermetic {
+    /// `declared` are the files the target lists in `sources` and `inputs`, and `keep` the
+    /// variables it may see, such as those its profile sets.
+    pub fn new(action: &Action, declared: &[PathBuf], keep: &[String]) -> Result<Hermetic, String> {
+        let run = format!("catalyst-{}-{}", std::process::id(), RUNS.fetch_add(1, Ordering::Relaxed));
+        let strace = which("strace").filter(|_| consts::OS == "linux");
+        let trace = env::temp_dir().join(format!("{}.trace", run));
+        let sandbox = match strace {
+            Some(_) => None,
+            None => {
+                let dir = env::temp_dir().join(run);
+                link_all(&dir, declared.iter().chain(&action.inputs)).map_err(|err| format!("cannot create the sandbox {}: {}", dir.display(), err))?;
+                Some(dir)
+            }
+        };
+        Ok(Hermetic { action: action.clone(), keep: keep.to_vec(), strace, trace, sandbox })
+    }
+
+    pub fn command(&self) -> Command {
+        let mut command = match &self.strace {
+            Some(strace) => {
+                let mut command = Command::new(strace);
+                command.args(["-f", "-qq", "-e", "trace=open,openat,openat2,creat", "-o"]).arg(&self.trace).arg("--").arg(&self.action.program);
+                command
+            }
+            None => Command::new(&self.action.program)
+        };
+        command.args(&self.action.args).env_clear().envs(self.env());
+        if let Some(sandbox) = &self.sandbox {
+            command.current_dir(sandbox);
+        }
+        info!("Hermetic: {:?}", command);
+        command
+    }
+
+    /// Explains a failure, which in a sandbox is often a file that was not declared.
+    pub fn explain(&self, err: String) -> String {
+        match &self.sandbox {
+            Some(_) => format!("{} (in the hermetic sandbox only the files listed in `sources` and `inputs` exist)", err),
+            None => err
+        }
+    }
+
+    /// The files the action opened for reading, when it was traced.
+    pub fn reads(&self) -> Vec<PathBuf> {
+        match fs::read_to_string(&self.trace) {
+            Ok(trace) => parse_trace(&trace),
+            Err(err) if self.strace.is_some() => {
+                warn!("Cannot read the trace {}: {}", self.trace.display(), err);
+                Vec::new()
+            }
+            Err(_) => Vec::new()
+        }
+    }
+
+    fn env(&self) -> Vec<(String, String)> {
+        let mut env = Vec::new();
+        let program_dir = self.action.program.parent().map(|dir| dir.display().to_string()).unwrap_or_default();
+        if consts::OS == "windows" {
+            for name in WINDOWS_ENV {
+                env.extend(env::var(name).ok().map(|value| (name.to_string(), value)));
+            }
+            let system = env::var("SYSTEMROOT").unwrap_or("C:\\Windows".to_string());
+            env.push(("PATH".to_string(), format!("{};{}\\System32", program_dir, system)));
+        } else {
+            env.push(("PATH".to_string(), format!("{}:{}", program_dir, UNIX_PATH)));
+        }
+        env.extend([("LANG", "C"), ("LC_ALL", "C"), ("TZ", "UTC")].map(|(name, value)| (name.to_string(), value.to_string())));
+        for name in &self.keep {
+            env.extend(env::var(name).ok().map(|value| (name.clone(), value)));
+        }
+        env.extend(self.action.env.iter().cloned());
+        env
+    }
+}
+
+impl Drop for Hermetic {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.trace);
+        if let Some(sandbox) = &self.sandbox {
+            let _ = fs::remove_dir_all(sandbox);
+        }
+    }
+}
+
+/// Links each relative path of `files` into `dir` at the same place. Absolute paths, such as
+/// outputs of earlier actions, are reachable from the sandbox as they are.
+fn link_all<'a>(dir: &Path, files: impl Iterator<Item = &'a PathBuf>) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let cwd = env::current_dir()?;
+    for file in files.filter(|file| file.is_relative()) {
+        let link = dir.join(util::normalize(file));
+        if link.exists() || !link.starts_with(dir) {
+            continue;
+        }
+        if let Some(parent) = link.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(cwd.join(file), &link)?;
+        #[cfg(windows)]
+        if std::os::windows::fs::symlink_file(cwd.join(file), &link).is_err() {
+            fs::copy(cwd.join(file), &link)?;
+        }
+    }
+    Ok(())
+}
+
+/// The files opened read-only in an strace log, following calls that strace split in two
+/// when another process interrupted them.
+fn parse_trace(trace: &str) -> Vec<PathBuf> {
+    let mut reads: Vec<PathBuf> = Vec::new();
+    let mut pending: HashMap<&str, &str> = HashMap::new();
+    for line in trace.lines() {
+        let (pid, call) = line.split_once(char::is_whitespace).map(|(pid, call)| (pid, call.trim_start())).unwrap_or(("", line));
+        let call = if call.contains("<unfinished ...>") {
+            pending.insert(pid, call.trim_end_matches("<unfinished ...>"));
+            continue;
+        } else if call.starts_with("<...") {
+            let Some(start) = pending.remove(pid) else { continue };
+            let Some((_, rest)) = call.split_once("resumed>") else { continue };
+            format!("{}{}", start, rest)
+        } else {
+            call.to_string()
+        };
+
+        let Some((arguments, result)) = call.rsplit_once(" = ") else { continue };
+        if result.starts_with('-') || arguments.contains("O_WRONLY") || arguments.contains("O_CREAT") || arguments.starts_with("creat(") {
+            continue;
+        }
+        let Some(path) = arguments.split('"').nth(1) else { continue };
+        let path = PathBuf::from(path);
+        if !reads.contains(&path) && path.is_file() {
+            reads.push(path);
+        }
+    }
+    reads
+}
+
+/// The files among `read` that lie in the working directory but are neither `declared`
+/// nor inputs or outputs of `action`.
+pub fn undeclared(read: &[PathBuf], declared: &[PathBuf], action: &Action) -> Vec<PathBuf> {
+    let cwd = env::current_dir().unwrap_or_default();
+    let local = |path: &PathBuf| {
+        let path = util::normalize(&cwd.join(path));
+        path.strip_prefix(&cwd).ok().map(Path::to_path_buf)
+    };
+    let known = declared.iter().chain(&action.inputs).chain(&action.outputs).chain(&action.depfile).filter_map(local).collect::<Vec<_>>();
+    let mut undeclared = Vec::new();
+    for path in read.iter().filter_map(local) {
+        let catalyst = path.starts_with(crate::config::CONFIG_DIR);
+        if !catalyst && !known.contains(&path) && !undeclared.contains(&path) {
+            undeclared.push(path);
+        }
+    }
+    undeclared
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_files_opened_for_reading() {
+        let trace = "\
+101   openat(AT_FDCWD, \"Cargo.toml\", O_RDONLY|O_CLOEXEC) = 3
+101   openat(AT_FDCWD, \"missing.h\", O_RDONLY) = -1 ENOENT (No such file or directory)
+101   openat(AT_FDCWD, \"src/main.rs\", O_WRONLY|O_CREAT|O_TRUNC, 0644) = 4
+101   stat(\"src\", {st_mode=S_IFDIR|0755, ...}) = 0
+101   open(\"Cargo.toml\", O_RDONLY) = 5
+";
+        assert_eq!(parse_trace(trace), [PathBuf::from("Cargo.toml")]);
+    }
+
+    #[test]
+    fn joins_interrupted_calls() {
+        let trace = "\
+101   openat(AT_FDCWD, \"src/main.rs\", O_RDONLY <unfinished ...>
+102   openat(AT_FDCWD, \"Cargo.toml\", O_RDONLY|O_CREAT <unfinished ...>
+101   <... openat resumed>) = 3
+102   <... openat resumed>) = 4
+103   <... openat resumed>) = 5
+";
+        assert_eq!(parse_trace(trace), [PathBuf::from("src/main.rs")]);
+    }
+}
@@ -0,0 +1,671 @@
+use std::{collections::{BTreeMap, HashSet}, env::consts, fs, path::{Path, PathBuf}};
+use ignore::WalkBuilder;
+use log::{error, info, warn};
+use owo_colors::{OwoColorize, Stream::Stdout};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::{compiler::which, config::{Project, CONFIG_DIR}, executor::{self, Options, Task}, graph, jscript, lua, profiles::DEFAULT_PROFILE, structs::{HookEntry, HookPhase}, CATALYST_VERSION};
+
+pub const HOOKS_DIR: &str = "hooks";
+/// Lists files hook discovery skips, like a `.gitignore` that only applies to Catalyst.
+pub const IGNORE_FILE: &str = ".clyignore";
+/// Opens and closes the header at the top of a hook file.
+const HEADER_FENCE: &str = "---";
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum HookLanguage {
+    Lua,
+    #[serde(alias = "javascript")]
+    Js
+}
+
+impl HookLanguage {
+    pub fn name(&self) -> &'static str {
+        match self {
+            HookLanguage::Lua => "lua",
+            HookLanguage::Js => "js"
+        }
+    }
+}
+
+/// The YAML between the `---` lines that open a hook file.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Header {
+    language: HookLanguage,
+    name: Option<String>,
+    description: Option<String>,
+    phase: Option<HookPhase>,
+    #[serde(default)]
+    depends_on: Vec<String>,
+    #[serde(default)]
+    platforms: Vec<String>,
+    #[serde(default)]
+    requires: Vec<String>,
+    min_version: Option<String>,
+    #[serde(default)]
+    params: BTreeMap<String, Param>
+}
+
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum ParamType {
+    #[default]
+    String,
+    Integer,
+    Number,
+    Boolean
+}
+
+impl ParamType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ParamType::String => "string",
+            ParamType::Integer => "integer",
+            ParamType::Number => "number",
+            ParamType::Boolean => "boolean"
+        }
+    }
+
+    /// Reads a value given on the command line.
+    fn parse(&self, text: &str) -> Option<Value> {
+        match self {
+            ParamType::String => Some(Value::from(text)),
+            ParamType::Integer => text.parse::<i64>().ok().map(Value::from),
+            ParamType::Number => text.parse::<f64>().ok().and_then(serde_json::Number::from_f64).map(Value::Number),
+            ParamType::Boolean => match text {
+                "true" | "yes" | "1" => Some(Value::Bool(true)),
+                "false" | "no" | "0" => Some(Value::Bool(false)),
+                _ => None
+            }
+        }
+    }
+
+    fn accepts(&self, value: &Value) -> bool {
+        match self {
+            ParamType::String => value.is_string(),
+            ParamType::Integer => value.is_i64() || value.is_u64(),
+            ParamType::Number => value.is_number(),
+            ParamType::Boolean => value.is_boolean()
+        }
+    }
+}
+
+/// A parameter declared under `params` in a hook's header.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Param {
+    #[serde(default, rename = "type")]
+    pub kind: ParamType,
+    /// Without one, the parameter must be given.
+    pub default: Option<Value>,
+    pub description: Option<String>
+}
+
+/// What the command line passes to a hook: the arguments after `--`, and `--set` pairs.
+#[derive(Clone, Default)]
+pub struct HookArgs {
+    pub args: Vec<String>,
+    pub set: Vec<(String, String)>
+}
+
+/// A hook file, parsed once into its metadata and its script.
+///
+/// The metadata comes from a header such as
+///
+/// ```text
+/// ---
+/// language: lua
+/// description: Generates the protocol sources
+/// phase: pre-build
+/// requires: [protoc]
+/// ---
+/// ```
+///
+/// or, in older hooks, from a first line reading `use lua` or `use js`.
+#[derive(Clone, Debug)]
+pub struct Hook {
+    pub path: PathBuf,
+    /// Defaults to the file name without `.cly`.
+    pub name: String,
+    pub language: HookLanguage,
+    pub description: Option<String>,
+    /// Used when the configuration lists the hook without a phase.
+    pub phase: Option<HookPhase>,
+    /// Names of hooks that run before this one.
+    pub depends_on: Vec<String>,
+    /// Values of `std::env::consts::OS` or `FAMILY`, e.g. `linux` or `unix`. Empty for all.
+    pub platforms: Vec<String>,
+    /// Programs that must be on `PATH`.
+    pub requires: Vec<String>,
+    pub min_version: Option<String>,
+    pub params: BTreeMap<String, Param>,
+    /// The file with its header blanked out, so that line numbers in errors still match.
+    pub script: String
+}
+
+impl Hook {
+    pub fn load(path: &Path) -> Result<Hook, String> {
+        let content = fs::read_to_string(path).map_err(|err| format!("cannot read {}: {}", path.display(), err))?;
+        Hook::parse(path, &content).map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    pub fn parse(path: &Path, content: &str) -> Result<Hook, String> {
+        let lines = content.lines().collect::<Vec<_>>();
+        let first = lines.first().map(|line| line.trim()).unwrap_or_default();
+        let (header, skip) = if first == HEADER_FENCE {
+            let end = lines.iter().skip(1).position(|line| line.trim_end() == HEADER_FENCE).ok_or(format!("the header has no closing `{}`", HEADER_FENCE))? + 1;
+            let header = serde_yaml::from_str::<Header>(&lines[1..end].join("\n")).map_err(|err| format!("invalid header: {}", err))?;
+            (header, end + 1)
+        } else {
+            // Quoted, the legacy line is also a valid statement in either language.
+            let language = match first.trim_matches('"') {
+                "use lua" => HookLanguage::Lua,
+                "use js" => HookLanguage::Js,
+                _ => return Err(format!("expected a `{}` header or a first line reading `use lua` or `use js`", HEADER_FENCE))
+            };
+            (Header { language, name: None, description: None, phase: None, depends_on: Vec::new(), platforms: Vec::new(), requires: Vec::new(), min_version: None, params: BTreeMap::new() }, 1)
+        };
+
+        for (name, param) in &header.params {
+            if param.default.as_ref().is_some_and(|default| !param.kind.accepts(default)) {
+                return Err(format!("the default of parameter `{}` is not of type {}", name, param.kind.name()))
+            }
+        }
+        let name = header.name.unwrap_or_else(|| path.file_stem().unwrap_or_default().to_string_lossy().to_string());
+        let script = "\n".repeat(skip) + &lines[skip.min(lines.len())..].join("\n");
+        Ok(Hook {
+            path: path.to_path_buf(),
+            name,
+            language: header.language,
+            description: header.description,
+            phase: header.phase,
+            depends_on: header.depends_on,
+            platforms: header.platforms,
+            requires: header.requires,
+            min_version: header.min_version,
+            params: header.params,
+            script
+        })
+    }
+
+    /// The values of the hook's parameters, from `--set` and from `--name value`, `--name=value`
+    /// or, for booleans, `--name` among the arguments, with defaults for the rest. A hook that
+    /// declares no parameters gets whatever `--set` gives, as strings.
+    pub fn params(&self, given: &HookArgs) -> Result<BTreeMap<String, Value>, String> {
+        if self.params.is_empty() {
+            return Ok(given.set.iter().map(|(name, value)| (name.clone(), Value::from(value.as_str()))).collect())
+        }
+
+        let mut raw = Vec::new();
+        let mut args = given.args.iter();
+        while let Some(arg) = args.next() {
+            let Some(option) = arg.strip_prefix("--") else { continue };
+            let (name, value) = match option.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (option, None)
+            };
+            // Other arguments are left for the script to read from `args`.
+            let Some(param) = self.params.get(name) else { continue };
+            let value = match value {
+                None if param.kind != ParamType::Boolean => args.next().cloned(),
+                value => value
+            };
+            raw.push((name.to_string(), value));
+        }
+        raw.extend(given.set.iter().map(|(name, value)| (name.clone(), Some(value.clone()))));
+
+        let mut params = BTreeMap::new();
+        for (name, text) in raw {
+            let Some(param) = self.params.get(&name) else {
+                let known = self.params.keys().cloned().collect::<Vec<_>>().join(", ");
+                return Err(format!("{} has no parameter `{}`, it takes {}", self.name, name, known))
+            };
+            let value = match text {
+                Some(text) => param.kind.parse(&text).ok_or(format!("parameter `{}` of {} must be of type {}, not `{}`", name, self.name, param.kind.name(), text))?,
+                None if param.kind == ParamType::Boolean => Value::Bool(true),
+                None => return Err(format!("--{} of {} needs a value", name, self.name))
+            };
+            params.insert(name, value);
+        }
+        for (name, param) in &self.params {
+            if !params.contains_key(name) {
+                let default = param.default.clone().ok_or(format!("{} needs the parameter `{}`, give it with --set {}=<value>", self.name, name, name))?;
+                params.insert(name.clone(), default);
+            }
+        }
+        Ok(params)
+    }
+
+    /// Whether the hook is meant to run on this platform.
+    pub fn runs_here(&self) -> bool {
+        self.platforms.is_empty() || self.platforms.iter().any(|platform| platform == consts::OS || platform == consts::FAMILY)
+    }
+
+    /// Why the hook cannot run, if a program it requires is missing or Catalyst is too old.
+    pub fn check(&self) -> Result<(), String> {
+        let missing = self.requires.iter().filter(|tool| which(tool).is_none()).cloned().collect::<Vec<_>>();
+        if !missing.is_empty() {
+            return Err(format!("{} requires {}, which cannot be found on PATH", self.name, missing.join(", ")))
+        }
+        if let Some(min_version) = &self.min_version {
+            let required = parse_version(min_version).ok_or(format!("{} has an invalid min_version `{}`", self.name, min_version))?;
+            if parse_version(CATALYST_VERSION) < Some(required) {
+                return Err(format!("{} requires Catalyst {} or newer, this is {}", self.name, min_version, CATALYST_VERSION))
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `1.2.3` as `[1, 2, 3]`, ignoring a pre-release suffix such as `-beta`.
+fn parse_version(version: &str) -> Option<Vec<u64>> {
+    let version = version.trim().trim_start_matches('v');
+    version.split(['-', '+']).next()?.split('.').map(|part| part.parse().ok()).collect()
+}
+
+/// What a running hook can see of the build that started it, exposed as the `catalyst` global.
+#[derive(Clone)]
+pub struct HookContext {
+    pub profile: String,
+    /// Given after `--` on the command line, exposed as `args`.
+    pub args: Vec<String>,
+    /// The hook's parameters, exposed as `params`.
+    pub params: BTreeMap<String, Value>
+}
+
+impl HookContext {
+    pub fn new(project: &Project) -> HookContext {
+        HookContext { profile: project.profile.name.clone(), ..HookContext::default() }
+    }
+}
+
+impl Default for HookContext {
+    /// For hooks run outside of a project.
+    fn default() -> HookContext {
+        HookContext { profile: DEFAULT_PROFILE.to_string(), args: Vec::new(), params: BTreeMap::new() }
+    }
+}
+
+/// Finds the file for a hook listed in the configuration.
+///
+/// Entries ending in `.cly` are paths relative to the project root, anything else is a
+/// name looked up as `<name>.cly` in the hook directories and then in the project root, and
+/// finally as the `name` in the header of a discovered hook.
+pub fn resolve(root: &Path, dirs: &[String], name: &str) -> Option<PathBuf> {
+    if name.ends_with(".cly") {
+        return Some(root.join(name)).filter(|path| path.is_file())
+    }
+    let file = format!("{}.cly", name);
+    search_dirs(root, dirs).into_iter()
+        .map(|dir| dir.join(&file))
+        .chain([root.join(&file)])
+        .find(|candidate| candidate.is_file())
+        .or_else(|| discover(root, dirs).into_iter().find(|path| Hook::load(path).is_ok_and(|hook| hook.name == name)))
+}
+
+/// The configured `hook_dirs` under `root`, or `.catalyst/hooks`.
+pub fn search_dirs(root: &Path, dirs: &[String]) -> Vec<PathBuf> {
+    if dirs.is_empty() {
+        return vec![root.join(CONFIG_DIR).join(HOOKS_DIR)]
+    }
+    dirs.iter().map(|dir| root.join(dir)).collect()
+}
+
+/// Every `.cly` file in the hook directories and below, sorted by path. Hidden files and
+/// those matched by a `.gitignore` or `.clyignore`, including one in a parent directory,
+/// are left out.
+pub fn discover(root: &Path, dirs: &[String]) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    for dir in search_dirs(root, dirs).into_iter().filter(|dir| dir.is_dir()) {
+        let walker = WalkBuilder::new(&dir)
+            .require_git(false)
+            .add_custom_ignore_filename(IGNORE_FILE)
+            .sort_by_file_name(|a, b| a.cmp(b))
+            .build();
+        for entry in walker {
+            match entry {
+                Ok(entry) if entry.file_type().is_some_and(|kind| kind.is_file()) && entry.path().extension().is_some_and(|extension| extension == "cly") => found.push(entry.into_path()),
+                Ok(_) => {}
+                Err(err) => warn!("Skipping part of {} while looking for hooks: {}", dir.display(), err)
+            }
+        }
+    }
+    found.sort();
+    found.dedup();
+    found
+}
+
+/// Prints the discovered hooks with their language, the phase they run in and their
+/// description, as a table or as JSON.
+pub fn list(project: &Project, json: bool) -> bool {
+    let entries = project.profile.hooks.as_ref().unwrap_or(&project.config.hooks);
+    let mut hooks = Vec::new();
+    let mut success = true;
+    for path in discover(&project.root, &project.config.hook_dirs) {
+        match Hook::load(&path) {
+            Ok(hook) => {
+                let entry = entries.iter().find(|entry| resolve(&project.root, &project.config.hook_dirs, entry.name()).is_some_and(|listed| same_file(&listed, &path)));
+                let phase = entry.and_then(HookEntry::phase).or(hook.phase).unwrap_or_default();
+                hooks.push((hook, phase));
+            }
+            Err(err) => {
+                warn!("Invalid hook: {}", err);
+                if !json {
+                    println!("{}", format!("Invalid hook {}", err).if_supports_color(Stdout, |text| text.yellow()));
+                }
+                success = false;
+            }
+        }
+    }
+    let relative = |path: &Path| path.strip_prefix(&project.root).unwrap_or(path).display().to_string();
+
+    if json {
+        let hooks = hooks.iter().map(|(hook, phase)| json!({
+            "name": hook.name,
+            "language": hook.language.name(),
+            "phase": phase.name(),
+            "path": relative(&hook.path),
+            "description": hook.description,
+            "depends_on": hook.depends_on,
+            "params": hook.params.iter().map(|(name, param)| (name.clone(), json!({
+                "type": param.kind.name(),
+                "default": param.default,
+                "description": param.description
+            }))).collect::<serde_json::Map<_, _>>()
+        })).collect::<Vec<_>>();
+        println!("{}", serde_json::to_string_pretty(&hooks).unwrap());
+        return success
+    }
+    if hooks.is_empty() {
+        println!("{}", "No hooks found.".if_supports_color(Stdout, |text| text.yellow()));
+        return success
+    }
+    let width = |column: &dyn Fn(&(Hook, HookPhase)) -> usize, title: &str| hooks.iter().map(column).max().unwrap_or_default().max(title.len());
+    let name = width(&|(hook, _)| hook.name.len(), "Name");
+    let phase = width(&|(_, phase)| phase.name().len(), "Phase");
+    let path = width(&|(hook, _)| relative(&hook.path).len(), "Path");
+    println!("{:name$}  {:8}  {:phase$}  {:path$}  Description", "Name", "Language", "Phase", "Path");
+    for (hook, hook_phase) in &hooks {
+        let line = format!("{:name$}  {:8}  {:phase$}  {:path$}  {}", hook.name, hook.language.name(), hook_phase.name(), relative(&hook.path), hook.description.as_deref().unwrap_or_default());
+        println!("{}", line.trim_end());
+    }
+    success
+}
+
+/// Runs the hooks listed in the configuration, or in the active profile, whose phase is one
+/// of `phases`, together with the hooks they depend on. Phases run in order, and within a
+/// phase each hook waits for the ones listed before it unless `depends_on` asks otherwise;
+/// only the dependencies they pull in can run side by side.
+pub fn run_all(project: &Project, options: Options, phases: &[HookPhase], ran: &mut HashSet<PathBuf>) -> bool {
+    let entries = project.profile.hooks.as_ref().unwrap_or(&project.config.hooks);
+    let mut listed = Vec::new();
+    for entry in entries {
+        let Some(path) = resolve(&project.root, &project.config.hook_dirs, entry.name()) else {
+            error!("Hook not found: {}", entry.name());
+            println!("{}", format!("Hook not found: {}", entry.name()).if_supports_color(Stdout, |text| text.red()));
+            return false
+        };
+        let hook = match Hook::load(&path) {
+            Ok(hook) => hook,
+            Err(err) => {
+                error!("Invalid hook: {}", err);
+                println!("{}", format!("Invalid hook {}", err).if_supports_color(Stdout, |text| text.red()));
+                return false
+            }
+        };
+        // The configuration decides the phase over the hook's own header.
+        listed.push((entry.phase().or(hook.phase).unwrap_or_default(), hook));
+    }
+    // Stable, so hooks keep their listed order within a phase.
+    listed.sort_by_key(|(phase, _)| *phase);
+
+    let roots = listed.iter().enumerate().filter(|(_, (phase, _))| phases.contains(phase)).map(|(i, _)| i).collect::<Vec<_>>();
+    if roots.is_empty() {
+        return true
+    }
+    let hooks = listed.into_iter().map(|(_, hook)| hook).collect();
+    info!("Running {} hooks...", phases.iter().map(HookPhase::name).collect::<Vec<_>>().join(", "));
+    let scheduled = schedule(&project.root, &project.config.hook_dirs, hooks, &roots, ran);
+    if let Ok(hooks) = &scheduled {
+        ran.extend(hooks.iter().map(|(hook, _)| identity(&hook.path)));
+    }
+    execute(scheduled, &HookContext::new(project), options, None)
+}
+
+/// Runs the hook file at `path` with `given` after the hooks it depends on, which are looked
+/// up in `project`, or from the working directory outside of one, and run without arguments.
+pub fn run_one(project: Option<&Project>, path: &Path, given: &HookArgs, context: &HookContext, options: Options) -> bool {
+    let (root, dirs) = match project {
+        Some(project) => (project.root.as_path(), project.config.hook_dirs.as_slice()),
+        None => (Path::new("."), [].as_slice())
+    };
+    match Hook::load(path) {
+        Ok(hook) => execute(schedule(root, dirs, vec![hook], &[0], &HashSet::new()), context, options, Some((path, given))),
+        Err(err) => {
+            error!("Invalid hook: {}", err);
+            println!("{}", format!("Invalid hook {}", err).if_supports_color(Stdout, |text| text.red()));
+            false
+        }
+    }
+}
+
+/// The hooks at `roots` and everything they depend on, each after its dependencies and
+/// otherwise in the order of `hooks`, with the indices of the hooks it waits for. Dependencies
+/// missing from `hooks` are resolved from `root` and `dirs`. Each root also waits for the roots
+/// before it, which come in phase and then listed order, unless a dependency runs the other
+/// way. Hooks in `ran` already ran earlier in the invocation and are left out.
+fn schedule(root: &Path, dirs: &[String], mut hooks: Vec<Hook>, roots: &[usize], ran: &HashSet<PathBuf>) -> Result<Vec<(Hook, Vec<usize>)>, String> {
+    let mut deps = Vec::new();
+    // Grows as dependencies are loaded, which get their own turn.
+    let mut i = 0;
+    while i < hooks.len() {
+        let mut edges = Vec::new();
+        for name in hooks[i].depends_on.clone() {
+            let path = resolve(root, dirs, &name);
+            let same = |hook: &Hook| hook.name == name || path.as_ref().is_some_and(|path| same_file(&hook.path, path));
+            let index = match hooks.iter().position(same) {
+                Some(index) => index,
+                None => {
+                    let path = path.ok_or(format!("{} depends on {}, which does not exist", hooks[i].name, name))?;
+                    hooks.push(Hook::load(&path)?);
+                    hooks.len() - 1
+                }
+            };
+            edges.push(index);
+        }
+        deps.push(edges);
+        i += 1;
+    }
+
+    graph::order(&deps).map_err(|cycle| {
+        let cycle = cycle.iter().map(|i| hooks[*i].name.as_str()).collect::<Vec<_>>();
+        format!("Hooks depend on each other: {}", cycle.join(" -> "))
+    })?;
+    for (at, &later) in roots.iter().enumerate() {
+        for &earlier in &roots[..at] {
+            if !graph::closure(&deps, &[earlier])[later] {
+                deps[later].push(earlier);
+            }
+        }
+    }
+    // Order edges only go where no path runs the other way, so no cycle is left to find.
+    let order = graph::order(&deps).unwrap_or_default();
+
+    let selected = graph::closure(&deps, roots);
+    let kept = order.into_iter().filter(|i| selected[*i] && !ran.contains(&identity(&hooks[*i].path))).collect::<Vec<_>>();
+    let mut position = vec![None; hooks.len()];
+    for (at, i) in kept.iter().enumerate() {
+        position[*i] = Some(at);
+    }
+    let mut hooks: Vec<Option<Hook>> = hooks.into_iter().map(Some).collect();
+    Ok(kept.into_iter().filter_map(|i| {
+        let edges = deps[i].iter().filter_map(|dep| position[*dep]).collect();
+        hooks[i].take().map(|hook| (hook, edges))
+    }).collect())
+}
+
+/// What `ran` in [`schedule`] keys a hook by, so one file reached by two paths counts once.
+fn identity(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or(path.to_path_buf())
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b
+    }
+}
+
+/// Runs each of `hooks` once the hooks it waits for succeeded, so independent hooks run side by
+/// side. Only the hook at the path in `target` gets the arguments given with it.
+fn execute(hooks: Result<Vec<(Hook, Vec<usize>)>, String>, context: &HookContext, options: Options, target: Option<(&Path, &HookArgs)>) -> bool {
+    let hooks = match hooks {
+        Ok(hooks) => hooks,
+        Err(err) => {
+            error!("{}", err);
+            println!("{}", err.if_supports_color(Stdout, |text| text.red()));
+            return false
+        }
+    };
+    let tasks = hooks.into_iter().map(|(hook, deps)| {
+        let given = match target {
+            Some((path, given)) if same_file(path, &hook.path) => given.clone(),
+            _ => HookArgs::default()
+        };
+        Task::new(hook.name.clone(), deps, move || {
+            if run(&hook, context, &given) {
+                Ok(())
+            } else {
+                println!("{}", format!("Hook failed: {}", hook.path.display()).if_supports_color(Stdout, |text| text.red()));
+                Err("hook failed".to_string())
+            }
+        })
+    }).collect();
+
+    let outcome = executor::execute(tasks, options);
+    outcome.summarize();
+    outcome.success()
+}
+
+/// Runs `hook` with `given`, or skips it on platforms it does not list.
+pub fn run(hook: &Hook, context: &HookContext, given: &HookArgs) -> bool {
+    if !hook.runs_here() {
+        info!("Skipping hook {}, it only runs on {}", hook.name, hook.platforms.join(", "));
+        println!("{}", format!("Skipping {}, it only runs on {}", hook.name, hook.platforms.join(", ")).if_supports_color(Stdout, |text| text.yellow()));
+        return true
+    }
+    let params = match hook.check().and_then(|_| hook.params(given)) {
+        Ok(params) => params,
+        Err(err) => {
+            error!("{}", err);
+            println!("{}", err.if_supports_color(Stdout, |text| text.red()));
+            return false
+        }
+    };
+    let context = &HookContext { args: given.args.clone(), params, ..context.clone() };
+
+    info!("{}", format!("Running hook: {} ({})", hook.path.display(), hook.language.name()).if_supports_color(Stdout, |text| text.cyan()));
+    if let Some(description) = &hook.description {
+        info!("{}: {}", hook.name, description);
+    }
+    let result = match hook.language {
+        HookLanguage::Lua => lua::run_lua(&hook.script, &hook.path.display().to_string(), context).map_err(|err| err.to_string()),
+        HookLanguage::Js => jscript::run_js(&hook.script, context)
+    };
+    match result {
+        Ok(_) => true,
+        Err(err) => {
+            error!("Hook {} failed: {}", hook.name, err);
+            println!("{}", err.if_supports_color(Stdout, |text| text.red()));
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "---\nlanguage: lua\nname: gen\nphase: post-build\ndepends_on: [fmt]\nparams:\n  channel:\n    default: stable\n  jobs:\n    type: integer\n  verbose:\n    type: boolean\n    default: false\n---\nprint(params.channel)\n";
+
+    fn hook() -> Hook {
+        Hook::parse(Path::new("hooks/generate.cly"), HEADER).unwrap()
+    }
+
+    fn given(args: &[&str], set: &[(&str, &str)]) -> HookArgs {
+        HookArgs {
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+            set: set.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+        }
+    }
+
+    #[test]
+    fn reads_the_header() {
+        let hook = hook();
+        assert_eq!(hook.name, "gen");
+        assert_eq!(hook.language, HookLanguage::Lua);
+        assert_eq!(hook.phase, Some(HookPhase::PostBuild));
+        assert_eq!(hook.depends_on, ["fmt"]);
+        assert_eq!(hook.params["jobs"].kind, ParamType::Integer);
+        assert_eq!(hook.params["channel"].kind, ParamType::String);
+        assert_eq!(hook.script.lines().position(|line| !line.is_empty()), Some(14));
+    }
+
+    #[test]
+    fn reads_the_legacy_first_line() {
+        let hook = Hook::parse(Path::new("hooks/fmt.cly"), "\"use js\"\nconsole.log(1)").unwrap();
+        assert_eq!(hook.name, "fmt");
+        assert_eq!(hook.language, HookLanguage::Js);
+        assert_eq!(hook.script, "\nconsole.log(1)");
+    }
+
+    #[test]
+    fn rejects_broken_headers() {
+        let path = Path::new("hook.cly");
+        assert_eq!(Hook::parse(path, "print(1)").unwrap_err(), "expected a `---` header or a first line reading `use lua` or `use js`");
+        assert_eq!(Hook::parse(path, "---\nlanguage: lua\n").unwrap_err(), "the header has no closing `---`");
+        assert!(Hook::parse(path, "---\nlanguage: lua\ncolour: red\n---\n").unwrap_err().starts_with("invalid header:"));
+        let error = Hook::parse(path, "---\nlanguage: lua\nparams:\n  jobs:\n    type: integer\n    default: many\n---\n").unwrap_err();
+        assert_eq!(error, "the default of parameter `jobs` is not of type integer");
+    }
+
+    #[test]
+    fn reads_params_from_arguments_and_set() {
+        let params = hook().params(&given(&["--jobs", "4", "--verbose", "extra"], &[("channel", "beta")])).unwrap();
+        assert_eq!(params["jobs"], 4);
+        assert_eq!(params["verbose"], true);
+        assert_eq!(params["channel"], "beta");
+
+        let params = hook().params(&given(&["--jobs=2", "--verbose=no"], &[])).unwrap();
+        assert_eq!(params["jobs"], 2);
+        assert_eq!(params["verbose"], false);
+        assert_eq!(params["channel"], "stable");
+    }
+
+    #[test]
+    fn checks_param_types() {
+        let error = hook().params(&given(&["--jobs", "four"], &[])).unwrap_err();
+        assert_eq!(error, "parameter `jobs` of gen must be of type integer, not `four`");
+        let error = hook().params(&given(&["--jobs=1", "--verbose=maybe"], &[])).unwrap_err();
+        assert_eq!(error, "parameter `verbose` of gen must be of type boolean, not `maybe`");
+        assert_eq!(hook().params(&given(&["--jobs"], &[])).unwrap_err(), "--jobs of gen needs a value");
+    }
+
+    #[test]
+    fn rejects_unknown_and_missing_params() {
+        let error = hook().params(&given(&[], &[("jobs", "1"), ("colour", "red")])).unwrap_err();
+        assert_eq!(error, "gen has no parameter `colour`, it takes channel, jobs, verbose");
+        let error = hook().params(&given(&[], &[])).unwrap_err();
+        assert_eq!(error, "gen needs the parameter `jobs`, give it with --set jobs=<value>");
+    }
+
+    #[test]
+    fn passes_set_through_without_declared_params() {
+        let hook = Hook::parse(Path::new("hook.cly"), "use lua\n").unwrap();
+        let params = hook.params(&given(&["--jobs", "4"], &[("jobs", "8")])).unwrap();
+        assert_eq!(params.into_iter().collect::<Vec<_>>(), [("jobs".to_string(), Value::from("8"))]);
+    }
+}
@@ -0,0 +1,172 @@
+use std::{cell::OnceCell, env, path::Path};
+use git2::Repository;
+use serde_json::Value;
+
+use crate::validate::{KeyPath, Problem, Segment};
+
+/// Expands `${...}` references in every string of a parsed config, in place.
+///
+/// Known variables are `env.NAME`, `project.name`, `project.version`, `profile`, `git.sha`
+/// and the entries of `vars`, either bare or as `vars.NAME`. `$${` escapes a literal `${`.
+pub fn expand(value: &mut Value, root: &Path, profile: &str) -> Vec<Problem> {
+    let scope = Scope {
+        root,
+        profile,
+        project: value.clone(),
+        git_sha: OnceCell::new()
+    };
+    let mut problems = Vec::new();
+    walk(value, &mut Vec::new(), &scope, &mut problems);
+    problems
+}
+
+fn walk(value: &mut Value, path: &mut Vec<Segment>, scope: &Scope, problems: &mut Vec<Problem>) {
+    match value {
+        Value::String(text) => match scope.expand(text, &mut Vec::new()) {
+            Ok(expanded) => *text = expanded,
+            Err((message, hint)) => problems.push(Problem::new(path, format!("{} in `{}`", message, KeyPath(path)), hint))
+        },
+        Value::Array(items) => {
+            for (i, item) in items.iter_mut().enumerate() {
+                path.push(Segment::Index(i));
+                walk(item, path, scope, problems);
+                path.pop();
+            }
+        }
+        Value::Object(map) => {
+            for (key, item) in map.iter_mut() {
+                path.push(Segment::Key(key.clone()));
+                walk(item, path, scope, problems);
+                path.pop();
+            }
+        }
+        _ => {}
+    }
+}
+
+type Failure = (String, Option<String>);
+
+struct Scope<'a> {
+    root: &'a Path,
+    profile: &'a str,
+    /// The config as written, which user variables and `project.*` are read from.
+    project: Value,
+    git_sha: OnceCell<Result<String, String>>
+}
+
+impl Scope<'_> {
+    fn expand(&self, text: &str, stack: &mut Vec<String>) -> Result<String, Failure> {
+        let mut expanded = String::new();
+        let mut rest = text;
+        while let Some(start) = rest.find('$') {
+            expanded.push_str(&rest[..start]);
+            rest = &rest[start..];
+            if let Some(after) = rest.strip_prefix("$${") {
+                expanded.push_str("${");
+                rest = after;
+            } else if let Some(after) = rest.strip_prefix("${") {
+                let end = after.find('}').ok_or((format!("unterminated `${{` in \"{}\"", text), Some("close it with `}` or escape it as `$${`".to_string())))?;
+                expanded.push_str(&self.lookup(after[..end].trim(), stack)?);
+                rest = &after[end + 1..];
+            } else {
+                expanded.push('$');
+                rest = &rest[1..];
+            }
+        }
+        expanded.push_str(rest);
+        Ok(expanded)
+    }
+
+    fn lookup(&self, name: &str, stack: &mut Vec<String>) -> Result<String, Failure> {
+        let undefined = || (format!("undefined variable `${{{}}}`", name), None);
+        match name.split_once('.') {
+            Some(("env", key)) => env::var(key).map_err(|_| (
+                format!("undefined variable `${{{}}}`", name),
+                Some(format!("set the {} environment variable", key))
+            )),
+            Some(("project", key @ ("name" | "version"))) => self.project.get(key).and_then(Value::as_str).map(str::to_string).ok_or_else(undefined),
+            Some(("git", "sha")) => self.git_sha().map_err(|err| (format!("cannot read `${{git.sha}}`: {}", err), None)),
+            Some(("vars", key)) => self.var(key, stack),
+            None if name == "profile" => Ok(self.profile.to_string()),
+            None => self.var(name, stack),
+            _ => Err(undefined())
+        }
+    }
+
+    fn var(&self, key: &str, stack: &mut Vec<String>) -> Result<String, Failure> {
+        let raw = self.project.get("vars").and_then(|vars| vars.get(key)).and_then(Value::as_str).ok_or((
+            format!("undefined variable `${{{}}}`", key),
+            Some(format!("define `{}` under `vars`", key))
+        ))?;
+        if stack.iter().any(|name| name == key) {
+            stack.push(key.to_string());
+            return Err((format!("variables refer to each other: {}", stack.join(" -> ")), None))
+        }
+        stack.push(key.to_string());
+        let value = self.expand(raw, stack);
+        stack.pop();
+        value
+    }
+
+    fn git_sha(&self) -> Result<String, String> {
+        self.git_sha.get_or_init(|| {
+            let repository = Repository::discover(self.root).map_err(|err| err.message().to_string())?;
+            let head = repository.head().and_then(|head| head.peel_to_commit()).map_err(|err| err.message().to_string())?;
+            Ok(head.id().to_string())
+        }).clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn expanded(mut value: Value) -> (Value, Vec<Problem>) {
+        let problems = expand(&mut value, Path::new("."), "release");
+        (value, problems)
+    }
+
+    #[test]
+    fn expands_known_variables() {
+        let (value, problems) = expanded(json!({
+            "name": "app",
+            "version": "1.2.0",
+            "vars": { "out": "build/${profile}", "file": "${vars.out}/${project.name}-${project.version}" },
+            "targets": [{ "output": "${file}" }]
+        }));
+        assert!(problems.is_empty());
+        assert_eq!(value["targets"][0]["output"], "build/release/app-1.2.0");
+    }
+
+    #[test]
+    fn keeps_escaped_and_lone_dollars() {
+        let (value, problems) = expanded(json!({ "name": "$${profile} costs $5" }));
+        assert!(problems.is_empty());
+        assert_eq!(value["name"], "${profile} costs $5");
+    }
+
+    #[test]
+    fn reports_unknown_variables_with_their_key() {
+        let (value, problems) = expanded(json!({ "name": "app", "targets": [{ "output": "${nope}" }] }));
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].path, [Segment::Key("targets".to_string()), Segment::Index(0), Segment::Key("output".to_string())]);
+        assert_eq!(problems[0].message, "undefined variable `${nope}` in `targets[0].output`");
+        assert_eq!(problems[0].hint.as_deref(), Some("define `nope` under `vars`"));
+        assert_eq!(value["targets"][0]["output"], "${nope}");
+    }
+
+    #[test]
+    fn reports_unknown_namespaces_and_unterminated_references() {
+        let (_, problems) = expanded(json!({ "a": "${project.license}", "b": "${profile" }));
+        assert_eq!(problems.len(), 2);
+        assert_eq!(problems[0].message, "undefined variable `${project.license}` in `a`");
+        assert_eq!(problems[1].message, "unterminated `${` in \"${profile\" in `b`");
+    }
+
+    #[test]
+    fn reports_variables_referring_to_each_other() {
+        let (_, problems) = expanded(json!({ "vars": { "a": "${b}", "b": "${a}" }, "name": "${a}" }));
+        assert!(problems.iter().any(|problem| problem.message == "variables refer to each other: a -> b -> a in `name`"));
+    }
+}
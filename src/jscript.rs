@@ -1,18 +1,23 @@
-use std::{env, fs, process::{self, Command}};
-use quick_js::{console, Context, JsValue, Callback};
-use git2::{Repository, SubmoduleUpdateOptions};
+use std::collections::HashMap;
+use quick_js::{console, Context, JsValue};
+use serde_json::Value;
 use log::{error, info, warn};
 
-use reqwest::Client;
+use crate::{hooks::HookContext, util::{find_file, prompt}};
 
-use crate::util::{extract_zip, find_file, package_zip, prompt, is_tool};
-
-pub fn run_js(script: String) {
+pub fn run_js(script: &str, hook: &HookContext) -> Result<(), String> {
     let context = Context::builder()
         .console(console::LogConsole)
         .build()
         .unwrap();
-    
+
+    let catalyst = HashMap::from([
+        ("profile".to_string(), JsValue::String(hook.profile.clone()))
+    ]);
+    context.set_global("catalyst", JsValue::Object(catalyst)).unwrap();
+    context.set_global("args", JsValue::Array(hook.args.iter().cloned().map(JsValue::String).collect())).unwrap();
+    context.set_global("params", JsValue::Object(hook.params.iter().map(|(name, value)| (name.clone(), js_value(value))).collect())).unwrap();
+
         context.add_callback("info", |msg: String| -> JsValue {
             info!("{}", msg);
             JsValue::Int(0)
@@ -42,11 +47,11 @@ pub fn run_js(script: String) {
 
         context.add_callback("prompt", |msg: String| -> JsValue {
             match prompt(msg) {
-                None => return JsValue::String(String::new()),
+                None => JsValue::String(String::new()),
                 Some(input) => {
-                    return JsValue::String(input.clone().trim().to_string())
+                    JsValue::String(input.clone().trim().to_string())
                 }
-            };
+            }
             
         }).unwrap();
 
@@ -70,9 +75,22 @@ pub fn run_js(script: String) {
             JsValue::Int(0)
         }).unwrap();
 
-    let script = script.lines().skip(1).collect::<Vec<_>>().join("\n");
+    context.eval(script)
+        .map(|_| ())
+        .map_err(|err| format!("failed to evaluate script: {}", err))
+}
 
-    context.eval(&script)
-        .map_err(|e| error!("Failed to evaluate script: {}", e))
-        .unwrap();
-}
\ No newline at end of file
+/// `value` as hooks see it in JavaScript. Whole numbers that fit become integers.
+fn js_value(value: &Value) -> JsValue {
+    match value {
+        Value::Null => JsValue::Null,
+        Value::Bool(value) => JsValue::Bool(*value),
+        Value::Number(number) => match number.as_i64().and_then(|number| i32::try_from(number).ok()) {
+            Some(number) => JsValue::Int(number),
+            None => JsValue::Float(number.as_f64().unwrap_or_default())
+        },
+        Value::String(value) => JsValue::String(value.clone()),
+        Value::Array(items) => JsValue::Array(items.iter().map(js_value).collect()),
+        Value::Object(map) => JsValue::Object(map.iter().map(|(key, value)| (key.clone(), js_value(value))).collect())
+    }
+}
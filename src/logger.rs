@@ -1,22 +1,12 @@
 use clap::ArgMatches;
 use log::LevelFilter;
-use std::{env::consts, fs, path::Path, process::exit};
+use std::{env::consts, fs, process::exit};
 use fern::colors::{Color, ColoredLevelConfig};
-use chrono;
-use whoami::username;
 
-pub fn setup_logger(matches: ArgMatches) -> Result<(), fern::InitError> {
-    let logdir_linux_path = format!("/home/{}/.catalyst/logs", username());
-    let logdir_windows_path = format!("C:\\Users\\{}\\AppData\\Local\\Temp\\Catalyst", username());
+use crate::util;
 
-    let logdir = match consts::OS {
-        "windows" => Path::new(logdir_windows_path.as_str()),
-        "linux" => Path::new(logdir_linux_path.as_str()),
-        _ => {
-            println!("Unsupported OS: {}", consts::OS);
-            exit(1);
-        }
-    };
+pub fn setup_logger(matches: ArgMatches) -> Result<(), fern::InitError> {
+    let logdir = util::logs_dir();
 
         if !logdir.exists() {
             if let Err(err) = fs::create_dir_all(&logdir) {
@@ -25,8 +15,8 @@ pub fn setup_logger(matches: ArgMatches) -> Result<(), fern::InitError> {
             }
         }
 
-        let logfile_win = format!("{}\\catalyst-{}.log", logdir.display(), chrono::offset::Utc::now().format("%Y-%m-%d_%H-%M-%S").to_string());
-        let logfile_linux = format!("{}/catalyst-{}.log", logdir.display(), chrono::offset::Utc::now().format("%Y-%m-%d_%H-%M-%S").to_string());
+        let logfile_win = format!("{}\\catalyst-{}.log", logdir.display(), chrono::offset::Utc::now().format("%Y-%m-%d_%H-%M-%S"));
+        let logfile_linux = format!("{}/catalyst-{}.log", logdir.display(), chrono::offset::Utc::now().format("%Y-%m-%d_%H-%M-%S"));
 
         let logfile = match consts::OS {
             "windows" => logfile_win,
@@ -68,7 +58,7 @@ pub fn setup_logger(matches: ArgMatches) -> Result<(), fern::InitError> {
                 "\x1B[{}m",
                 colors.get_color(&record.level()).to_fg_str()
             ),
-            date = chrono::offset::Utc::now().format("%Y-%m-%d_%H-%M-%S").to_string(),
+            date = chrono::offset::Utc::now().format("%Y-%m-%d_%H-%M-%S"),
             target = record.target(),
             level = colors.color(record.level()),
             message = message,
@@ -85,7 +75,7 @@ pub fn setup_logger(matches: ArgMatches) -> Result<(), fern::InitError> {
     .format(move |out, message, record| {
         out.finish(format_args!(
             "[{} {} {}] {}",
-            chrono::offset::Utc::now().format("%Y-%m-%d_%H-%M-%S").to_string(),
+            chrono::offset::Utc::now().format("%Y-%m-%d_%H-%M-%S"),
             record.level(),
             record.target(),
             message,
@@ -4,9 +4,10 @@ use git2::{Repository, SubmoduleUpdateOptions};
 use log::{error, info, warn};
 use reqwest::Client;
 
-use crate::util::{extract_zip, find_file, package_zip, prompt, is_tool};
+use crate::{hooks::HookContext, util::{extract_zip, find_file, package_zip, prompt, is_tool}};
 
-pub fn run_lua(path: String) -> Result<(), LuaError> {
+/// Runs a hook's script, named `chunk` in error messages.
+pub fn run_lua(script: &str, chunk: &str, context: &HookContext) -> Result<(), LuaError> {
     let lua = Lua::new();
     let fs = lua.create_table().unwrap();
     let git = lua.create_table().unwrap();
@@ -15,8 +16,20 @@ pub fn run_lua(path: String) -> Result<(), LuaError> {
     let http = lua.create_table().unwrap();
     let zip = lua.create_table().unwrap();
     let log = lua.create_table().unwrap();
+    let catalyst = lua.create_table().unwrap();
     let globals = lua.globals();
 
+    catalyst.set("profile", context.profile.clone()).unwrap();
+
+    let args = lua.create_table().unwrap();
+    let params = lua.create_table().unwrap();
+    for (i, arg) in context.args.iter().enumerate() {
+        args.set(i + 1, arg.clone()).unwrap();
+    }
+    for (name, value) in &context.params {
+        params.set(name.clone(), lua.to_value(value)?).unwrap();
+    }
+
     log.set("info", lua.create_function(move |_, msg: String| {
         info!("{}", msg);
         Ok(())
@@ -65,13 +78,16 @@ pub fn run_lua(path: String) -> Result<(), LuaError> {
     })?).unwrap();
 
     globals.set("shell", lua.create_function(move |_, (shell, command): (String, String)| {
-        Command::new(shell)
+        let status = Command::new(&shell)
         .arg("-c")
-        .arg(command)
-        .spawn()
-        .expect("failed to execute process");
+        .arg(&command)
+        .status()
+        .map_err(|err| mlua::Error::external(format!("Failed to run {}: {}", shell, err)))?;
 
-        Ok(())
+        match status.success() {
+            true => Ok(()),
+            false => Err(mlua::Error::external(format!("`{}` failed with {}", command, status)))
+        }
     })?).unwrap();
 
     os.set("getenv", lua.create_function(move |_, key: String| {
@@ -96,7 +112,8 @@ pub fn run_lua(path: String) -> Result<(), LuaError> {
     })?).unwrap();
 
     fs.set("mkdir", lua.create_function(move |_, path: String| {
-        Ok(fs::create_dir_all(path).unwrap())
+        fs::create_dir_all(path).unwrap();
+        Ok(())
     })?).unwrap();
 
     fs.set("exists", lua.create_function(move |_, path: String| {
@@ -183,19 +200,9 @@ pub fn run_lua(path: String) -> Result<(), LuaError> {
     let _ = globals.set("http", http);
     let _ = globals.set("zip", zip);
     let _ = globals.set("log", log);
+    let _ = globals.set("catalyst", catalyst);
+    let _ = globals.set("args", args);
+    let _ = globals.set("params", params);
 
-    let script_content = fs::read_to_string(path);
-
-    match script_content {
-        Err(err) => {
-            error!("Failed to read script: {}", err);
-            process::exit(1);
-        }
-
-        Ok(chunk) => {
-            let script = chunk.lines().skip(1).collect::<Vec<_>>().join("\n");
-            lua.load(script.as_str()).exec().expect("Failed to run script");
-        }
-    }
-    Ok(())
-}
\ No newline at end of file
+    lua.load(script).set_name(chunk).exec()
+}
@@ -1,8 +1,7 @@
-use std::{env::consts, fs, path::Path, process};
-use glob::glob;
+use std::{collections::HashSet, path::Path, process};
+use clap::ArgMatches;
 use owo_colors::{OwoColorize, Stream::Stdout};
 use log::{error, info};
-use util::find_file;
 
 mod structs;
 mod util;
@@ -11,6 +10,28 @@ mod debug;
 mod updater;
 mod lua;
 mod jscript;
+mod builder;
+mod config;
+mod hooks;
+mod validate;
+mod profiles;
+mod interpolate;
+mod graph;
+mod workspace;
+mod extends;
+mod edit;
+mod templates;
+mod compiler;
+mod cache;
+mod executor;
+mod depfile;
+mod compdb;
+mod clean;
+mod remote;
+mod hermetic;
+mod diagnostics;
+
+use structs::HookPhase;
 
 const CATALYST_VERSION: &str = env!("CARGO_PKG_VERSION");
 
@@ -29,32 +50,132 @@ fn main() {
     util::banner(matches.clone());
 
     match matches.subcommand() {
-        Some(("init", _)) => {
+        Some(("init", sub)) => {
             info!("Initializing configuration...");
-            let gensuccess = util::generate();
-            process::exit(gensuccess as i32);
+            let success = util::generate(sub);
+            process::exit(if success { 0 } else { 1 });
+        }
+        Some((command @ ("build" | "package"), sub)) => {
+            let projects = workspace::select(config::load(&matches), &matches);
+            let target = sub.get_one::<String>("target").map(|v| v.as_str());
+            if let Some(target) = target {
+                if !projects.iter().any(|project| project.config.targets.iter().any(|t| t.name == target)) {
+                    error!("No target named {} in the configuration", target);
+                    println!("{}", format!("Unknown target: {}", target).if_supports_color(Stdout, |text| text.red()));
+                    process::exit(1);
+                }
+            }
+            let options = executor::Options::from_matches(&matches);
+            let after: &[HookPhase] = if command == "package" { &[HookPhase::PostBuild, HookPhase::Package] } else { &[HookPhase::PostBuild] };
+            let mut success = true;
+            for project in &projects {
+                if target.is_some_and(|target| !project.config.targets.iter().any(|t| t.name == target)) {
+                    continue;
+                }
+                announce(project, projects.len());
+                project.enter();
+                if !build(project, sub, target, options, after) {
+                    success = false;
+                    if !options.keep_going {
+                        break;
+                    }
+                }
+            }
+            process::exit(if success { 0 } else { 1 });
+        }
+        Some(("compdb", _)) => {
+            let projects = workspace::select(config::load(&matches), &matches);
+            let mut success = true;
+            for project in &projects {
+                announce(project, projects.len());
+                project.enter();
+                success &= compdb::write(project);
+            }
+            process::exit(if success { 0 } else { 1 });
+        }
+        Some(("config", sub)) if sub.subcommand_name() == Some("schema") => {
+            println!("{}", serde_json::to_string_pretty(&validate::schema()).unwrap());
+            return;
+        }
+        Some(("config", sub)) => {
+            let project = config::load(&matches);
+            let success = match sub.subcommand() {
+                Some(("validate", _)) => {
+                    println!("{}", format!("{} is valid.", project.path.display()).if_supports_color(Stdout, |text| text.green()));
+                    true
+                }
+                Some(("show", args)) => config::show(&project, args.get_flag("resolved")),
+                Some(("get", args)) => edit::get(&project, args.get_one::<String>("key").unwrap(), args.get_flag("resolved")),
+                Some(("set", args)) => edit::set(&project, args.get_one::<String>("key").unwrap(), args.get_one::<String>("value").unwrap()),
+                Some(("unset", args)) => edit::unset(&project, args.get_one::<String>("key").unwrap()),
+                Some(("list", args)) => edit::list(&project, args.get_flag("resolved")),
+                Some(("convert", args)) => {
+                    let to = args.get_one::<String>("to").and_then(|to| config::Format::from_name(to)).unwrap();
+                    config::convert(&project, to, args.get_flag("keep"), args.get_flag("force"))
+                }
+                _ => unreachable!()
+            };
+            process::exit(if success { 0 } else { 1 });
+        }
+        Some(("run", sub)) => {
+            let project = config::load(&matches);
+            let name = sub.get_one::<String>("hook").unwrap();
+            let Some(path) = hooks::resolve(&project.root, &project.config.hook_dirs, name) else {
+                error!("Hook not found: {}", name);
+                println!("{}", format!("Hook not found: {}", name).if_supports_color(Stdout, |text| text.red()));
+                process::exit(1);
+            };
+            let given = hooks::HookArgs {
+                args: sub.get_many::<String>("args").unwrap_or_default().cloned().collect(),
+                set: sub.get_many::<(String, String)>("set").unwrap_or_default().cloned().collect()
+            };
+            project.enter();
+            let success = hooks::run_one(Some(&project), &path, &given, &hooks::HookContext::new(&project), executor::Options::from_matches(&matches));
+            process::exit(if success { 0 } else { 1 });
+        }
+        Some(("hooks", sub)) => {
+            let project = config::load(&matches);
+            let success = match sub.subcommand() {
+                Some(("list", args)) => hooks::list(&project, args.get_flag("json")),
+                _ => unreachable!()
+            };
+            process::exit(if success { 0 } else { 1 });
         }
         Some(("update", _)) => {
             updater::update(CATALYST_VERSION);
+            return;
         }
 
         Some(("check", _)) => {
             updater::check(CATALYST_VERSION);
+            return;
         }
-        Some(("cleanup", _)) => {
-            let logdir = if consts::OS == "windows" {
-                Path::new("C:\\Users\\%USERNAME%\\AppData\\Local\\Temp\\Catalyst")
-            } else {
-                Path::new("~/.catalyst/cache")
+        Some(("clean", sub)) => {
+            let options = clean::Options {
+                triple: sub.get_one::<String>("triple").cloned(),
+                profile: matches.get_one::<String>("profile").is_some(),
+                cache: sub.get_flag("cache"),
+                logs: sub.get_flag("logs"),
+                dry_run: sub.get_flag("dry_run")
             };
-            if Path::exists(logdir) {
-                match fs::remove_dir_all(logdir) {
-                    Ok(_) => {}
-                    Err(_) => {
-                        error!("Failed to remove logs directory");
+            let projects = if options.outputs() { workspace::select(config::load(&matches), &matches) } else { Vec::new() };
+            if !options.dry_run {
+                let hook_options = executor::Options::from_matches(&matches);
+                for project in &projects {
+                    project.enter();
+                    if !hooks::run_all(project, hook_options, &[HookPhase::Clean], &mut HashSet::new()) {
+                        process::exit(1);
                     }
                 }
             }
+            let success = match clean::paths(&projects, &options) {
+                Ok(paths) => clean::clean(&paths, options.dry_run),
+                Err(err) => {
+                    println!("{}", err.if_supports_color(Stdout, |text| text.red()));
+                    false
+                }
+            };
+            process::exit(if success { 0 } else { 1 });
         }
         _ => {}
     }
@@ -64,58 +185,56 @@ fn main() {
     }
 
 
-    let hook = matches.get_many::<String>("hook").unwrap_or_default().into_iter().map(|v| v.as_str()).collect::<Vec<_>>();
+    let hook = matches.get_many::<String>("hook").unwrap_or_default().map(|v| v.as_str()).collect::<Vec<_>>();
     if !hook.is_empty() {
-        let script = fs::read_to_string(hook[0]).unwrap();
-        let first_line = script.lines().next().unwrap_or("").to_string();
-        info!("{}", first_line);
-        if first_line == "\"use js\"" {
-            let _ = jscript::run_js(script);
-        }
-        else if first_line == "\"use lu\"a" {
-            let _ = lua::run_lua(script);
-        }
-        else {
-            error!("Invalid hook file");
-        }
+        let context = match matches.get_one::<String>("profile") {
+            Some(profile) => hooks::HookContext { profile: profile.clone(), ..hooks::HookContext::default() },
+            None => hooks::HookContext::default()
+        };
+        // Dependencies are looked up in the project around the working directory, if any.
+        let profile = matches.get_one::<String>("profile").map(|v| v.as_str()).unwrap_or(profiles::DEFAULT_PROFILE);
+        let project = config::discover(".").map(|path| config::open(&path, profile));
+        let given = hooks::HookArgs { args: matches.get_many::<String>("args").unwrap_or_default().cloned().collect(), set: Vec::new() };
+        let success = hooks::run_one(project.as_ref(), Path::new(hook[0]), &given, &context, executor::Options::from_matches(&matches));
+        process::exit(if success { 0 } else { 1 });
     }
-    else{
-
-        info!("Detecting hooks...");
-        let hooks = glob("**/*.cly").unwrap();
-
-    info!("Running hooks...");
-    for hook in hooks {
-        match hook {
-            Ok(hook) => {
-
-                let hook = hook.display().to_string();
-                info!("{}", format!("Running hook: {}", hook).to_string().if_supports_color(Stdout, |text| text.cyan()));
-                match fs::read_to_string(&hook) {
-                    Ok(content) => {
-                        let first_line = content.lines().next().unwrap_or("").to_string();
-                        info!("{}", first_line);
-                        if first_line == "use js" {
-                            let _ = jscript::run_js(content);
-                        }
-                        else if first_line == "use lua" {
-                            let _ = lua::run_lua(content);
-                        }
-                        else {
-                            error!("Invalid hook file");
-                        }
-                        
-                    },
-                    Err(_) => {
-                        error!("Failed to read hook file");
-                        continue;
-                    }
-                };
-            }
-            Err(_) => {
-                error!("Failed to run hook");
+    else {
+        let projects = workspace::select(config::load(&matches), &matches);
+        let options = executor::Options::from_matches(&matches);
+        let mut success = true;
+        for project in &projects {
+            announce(project, projects.len());
+            project.enter();
+            if !hooks::run_all(project, options, &[HookPhase::PreBuild, HookPhase::Build, HookPhase::PostBuild], &mut HashSet::new()) {
+                success = false;
+                if !options.keep_going {
+                    break;
+                }
             }
         }
+        process::exit(if success { 0 } else { 1 });
+    }
+}
+
+/// Runs the hooks before the build, builds `target` or every target for each triple asked
+/// for, and then runs the hooks of the phases in `after`. A hook both phases need runs once.
+fn build(project: &config::Project, sub: &ArgMatches, target: Option<&str>, options: executor::Options, after: &[HookPhase]) -> bool {
+    let mut ran = HashSet::new();
+    if !hooks::run_all(project, options, &[HookPhase::PreBuild, HookPhase::Build], &mut ran) {
+        return false
     }
+    let written = !sub.get_flag("compdb") || compdb::write(project);
+    let triples = match sub.get_many::<String>("triple") {
+        Some(triples) => triples.cloned().collect(),
+        None => project.config.matrix.clone()
+    };
+    builder::build_matrix(project, target, sub.get_flag("force"), sub.get_flag("hermetic"), options, &triples)
+        && hooks::run_all(project, options, after, &mut ran)
+        && written
+}
+
+fn announce(project: &config::Project, count: usize) {
+    if count > 1 {
+        println!("{}", format!("Project: {}", project.config.name).if_supports_color(Stdout, |text| text.purple()));
     }
 }
\ No newline at end of file
@@ -0,0 +1,71 @@
+use std::collections::BTreeMap;
+
+use crate::{config::{CONFIG_DIR, OUT_DIR}, structs::{Config, HookEntry, Profile}};
+
+pub const DEFAULT_PROFILE: &str = "debug";
+
+/// A profile with its `inherits` chain folded in.
+#[derive(Clone)]
+pub struct ActiveProfile {
+    pub name: String,
+    pub flags: Vec<String>,
+    pub env: BTreeMap<String, String>,
+    pub hooks: Option<Vec<HookEntry>>,
+    pub output_dir: String,
+    pub optimize: bool,
+    pub debug_info: bool
+}
+
+fn builtin(name: &str) -> Option<Profile> {
+    match name {
+        "debug" => Some(Profile { debug_info: Some(true), ..Profile::default() }),
+        "release" => Some(Profile { optimize: Some(true), ..Profile::default() }),
+        _ => None
+    }
+}
+
+/// The configured profile, layered over the built-in one of the same name.
+fn definition(config: &Config, name: &str) -> Option<Profile> {
+    match (builtin(name), config.profiles.get(name)) {
+        (Some(base), Some(profile)) => Some(layer(base, profile)),
+        (base, profile) => base.or(profile.cloned())
+    }
+}
+
+fn layer(mut base: Profile, profile: &Profile) -> Profile {
+    base.inherits = profile.inherits.clone().or(base.inherits);
+    base.flags.extend(profile.flags.iter().cloned());
+    base.env.extend(profile.env.clone());
+    base.hooks = profile.hooks.clone().or(base.hooks);
+    base.output_dir = profile.output_dir.clone().or(base.output_dir);
+    base.optimize = profile.optimize.or(base.optimize);
+    base.debug_info = profile.debug_info.or(base.debug_info);
+    base
+}
+
+pub fn resolve(config: &Config, name: &str) -> Result<ActiveProfile, String> {
+    let mut chain = vec![name.to_string()];
+    let mut current = definition(config, name).ok_or(format!("unknown profile `{}`", name))?;
+    let mut layers = vec![current.clone()];
+
+    while let Some(parent) = current.inherits.clone() {
+        if chain.contains(&parent) {
+            chain.push(parent);
+            return Err(format!("profiles inherit from each other: {}", chain.join(" -> ")))
+        }
+        current = definition(config, &parent).ok_or(format!("profile `{}` inherits from unknown profile `{}`", chain.last().unwrap(), parent))?;
+        chain.push(parent);
+        layers.push(current.clone());
+    }
+
+    let merged = layers.iter().rev().fold(Profile::default(), layer);
+    Ok(ActiveProfile {
+        name: name.to_string(),
+        flags: merged.flags,
+        env: merged.env,
+        hooks: merged.hooks,
+        output_dir: merged.output_dir.unwrap_or(format!("{}/{}/{}", CONFIG_DIR, OUT_DIR, name)),
+        optimize: merged.optimize.unwrap_or(false),
+        debug_info: merged.debug_info.unwrap_or(false)
+    })
+}
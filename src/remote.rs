@@ -0,0 +1,122 @@
+use std::{env, fs, path::PathBuf, sync::atomic::{AtomicBool, AtomicUsize, Ordering}, time::Duration};
+use log::{info, warn};
+use owo_colors::{OwoColorize, Stream::Stdout};
+use reqwest::{blocking::Client, StatusCode};
+
+use crate::{structs::{CacheConfig, CacheMode}, CATALYST_VERSION};
+
+/// Overrides `cache.remote`, so that a machine can use another store or none at all with `off`.
+pub const REMOTE_VAR: &str = "CATALYST_REMOTE_CACHE";
+/// Overrides `cache.mode`, e.g. `read-write` on the machine meant to fill the store.
+pub const MODE_VAR: &str = "CATALYST_REMOTE_CACHE_MODE";
+/// Sent as a bearer token to HTTP stores.
+pub const TOKEN_VAR: &str = "CATALYST_REMOTE_CACHE_TOKEN";
+
+/// Numbers temporary files, so that threads storing the same object do not collide.
+static WRITES: AtomicUsize = AtomicUsize::new(0);
+
+enum Store {
+    Dir(PathBuf),
+    Http { url: String, client: Client, token: Option<String> }
+}
+
+/// A build cache shared between machines: a directory, such as a network mount, or an HTTP
+/// server answering `GET` and `PUT`. Records live under `ac/` and outputs under `cas/`,
+/// named by their hash.
+pub struct Remote {
+    store: Store,
+    pub writable: bool,
+    /// Set after the first error, so that an unreachable store does not slow every action.
+    offline: AtomicBool
+}
+
+impl Remote {
+    /// The store configured under `cache`, with the environment taking precedence.
+    pub fn configure(config: Option<&CacheConfig>) -> Result<Option<Remote>, String> {
+        let location = env::var(REMOTE_VAR).ok().or(config.and_then(|config| config.remote.clone()));
+        let Some(location) = location.filter(|location| !location.is_empty() && location != "off") else { return Ok(None) };
+        let mode = match env::var(MODE_VAR) {
+            Ok(mode) => CacheMode::from_name(&mode).ok_or(format!("{} must be read-only or read-write, not `{}`", MODE_VAR, mode))?,
+            Err(_) => config.map(|config| config.mode).unwrap_or_default()
+        };
+
+        let store = if location.starts_with("http://") || location.starts_with("https://") {
+            let client = Client::builder()
+                .timeout(Duration::from_secs(30))
+                .user_agent(format!("Catalyst/{}", CATALYST_VERSION))
+                .build()
+                .map_err(|err| format!("cannot create an HTTP client: {}", err))?;
+            Store::Http { url: location.trim_end_matches('/').to_string(), client, token: env::var(TOKEN_VAR).ok() }
+        } else {
+            Store::Dir(PathBuf::from(location.strip_prefix("file://").unwrap_or(&location)))
+        };
+        info!("Remote cache: {} ({})", location, mode.name());
+        Ok(Some(Remote { store, writable: mode == CacheMode::ReadWrite, offline: AtomicBool::new(false) }))
+    }
+
+    /// The object at `name`, or `None` when the store does not have it.
+    pub fn get(&self, name: &str) -> Option<Vec<u8>> {
+        if self.offline.load(Ordering::Relaxed) {
+            return None
+        }
+        let result = match &self.store {
+            Store::Dir(dir) => match fs::read(dir.join(name)) {
+                Ok(bytes) => Ok(Some(bytes)),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(err) => Err(err.to_string())
+            },
+            Store::Http { url, client, token } => {
+                let mut request = client.get(format!("{}/{}", url, name));
+                if let Some(token) = token {
+                    request = request.bearer_auth(token);
+                }
+                match request.send() {
+                    Ok(response) if response.status() == StatusCode::NOT_FOUND => Ok(None),
+                    Ok(response) if response.status().is_success() => response.bytes().map(|bytes| Some(bytes.to_vec())).map_err(|err| err.to_string()),
+                    Ok(response) => Err(format!("GET {} returned {}", name, response.status())),
+                    Err(err) => Err(err.to_string())
+                }
+            }
+        };
+        result.unwrap_or_else(|err| {
+            self.disable(&err);
+            None
+        })
+    }
+
+    /// Stores `bytes` at `name`. Does nothing on a read-only store.
+    pub fn put(&self, name: &str, bytes: &[u8]) -> bool {
+        if !self.writable || self.offline.load(Ordering::Relaxed) {
+            return false
+        }
+        let result = match &self.store {
+            Store::Dir(dir) => {
+                let path = dir.join(name);
+                let tmp = path.with_extension(format!("tmp{}-{}", std::process::id(), WRITES.fetch_add(1, Ordering::Relaxed)));
+                path.parent().map_or(Ok(()), fs::create_dir_all)
+                    .and_then(|_| fs::write(&tmp, bytes))
+                    .and_then(|_| fs::rename(&tmp, &path))
+                    .map_err(|err| err.to_string())
+            }
+            Store::Http { url, client, token } => {
+                let mut request = client.put(format!("{}/{}", url, name)).body(bytes.to_vec());
+                if let Some(token) = token {
+                    request = request.bearer_auth(token);
+                }
+                match request.send() {
+                    Ok(response) if response.status().is_success() => Ok(()),
+                    Ok(response) => Err(format!("PUT {} returned {}", name, response.status())),
+                    Err(err) => Err(err.to_string())
+                }
+            }
+        };
+        result.inspect_err(|err| self.disable(err)).is_ok()
+    }
+
+    fn disable(&self, err: &str) {
+        if !self.offline.swap(true, Ordering::Relaxed) {
+            warn!("Remote cache unavailable for the rest of the build: {}", err);
+            println!("{}", format!("Remote cache unavailable for the rest of the build: {}", err).if_supports_color(Stdout, |text| text.yellow()));
+        }
+    }
+}
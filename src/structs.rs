@@ -1,9 +1,273 @@
+use std::collections::BTreeMap;
+use schemars::JsonSchema;
 use serde::{ Serialize, Deserialize };
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, JsonSchema, Default)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
+    /// Base configs merged under this one, in order: paths relative to this file, or
+    /// `git+<url>#<ref>:<path>`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extends: Vec<String>,
     pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub version: Option<String>,
+    /// Relative to the directory containing `.catalyst/`.
+    #[serde(default)]
     pub working_directory: String,
-    pub hooks: Vec<String>
+    /// Hook names or `.cly` paths, run in order within their phase.
+    #[serde(default)]
+    pub hooks: Vec<HookEntry>,
+    /// Directories searched for hooks, relative to the project root. Defaults to `.catalyst/hooks`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hook_dirs: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub targets: Vec<Target>,
+    /// Build profiles selected with `--profile`. `debug` and `release` always exist.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, Profile>,
+    /// User variables, available as `${name}` or `${vars.name}` in any string value.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub vars: BTreeMap<String, String>,
+    /// Compilers for languages without a built-in driver, or replacing a built-in one.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub drivers: Vec<DriverConfig>,
+    /// Target triples `cly build` builds for when no `--target` is given.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub matrix: Vec<String>,
+    /// Cross toolchains by target triple.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub toolchains: BTreeMap<String, Toolchain>,
+    /// A build cache shared with other machines.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache: Option<CacheConfig>,
+    /// Makes this project the root of a workspace of other Catalyst projects.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspace: Option<Workspace>
+}
+
+/// A compilation unit declared in the configuration, built by `cly build`.
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Target {
+    pub name: String,
+    /// Glob patterns, relative to the working directory.
+    pub sources: Vec<String>,
+    /// Detected from the first source file when omitted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// Overrides the default compiler for the language.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compiler: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub flags: Vec<String>,
+    #[serde(default)]
+    pub kind: OutputKind,
+    /// Relative to the output directory of the profile and triple, defaults to the platform's file name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    /// Glob patterns of other files the compiler may read, such as headers or data files.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub inputs: Vec<String>,
+    /// Runs the target's actions with a scrubbed environment and reports the files they read
+    /// that are not declared in `sources` or `inputs`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub hermetic: bool
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, Default, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputKind {
+    #[default]
+    Executable,
+    StaticLib,
+    SharedLib
+}
+
+/// Settings layered on top of every target. Fields left out are taken from `inherits`.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    /// Profile this one starts from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inherits: Option<String>,
+    /// Appended to the flags of every target, after the inherited ones.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub flags: Vec<String>,
+    /// Set for compilers and hooks, merged over the inherited ones.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// Replaces the `hooks` list while the profile is active.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hooks: Option<Vec<HookEntry>>,
+    /// Relative to the project root, defaults to `.catalyst/out/<profile>`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_dir: Option<String>,
+    /// Adds the compiler's optimization flags.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub optimize: Option<bool>,
+    /// Adds the compiler's debug information flags.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub debug_info: Option<bool>
+}
+
+/// Teaches `cly build` a compiler, used for targets whose language matches.
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct DriverConfig {
+    /// The language as detected, e.g. `Nim` or `C++`.
+    pub language: String,
+    /// Programs tried in order on `PATH`.
+    pub compilers: Vec<String>,
+    /// `{flags}` and `{sources}` expand to one argument each; `{source}` (the first source),
+    /// `{output}`, `{output_dir}` and `{name}` are replaced inside arguments.
+    pub args: Vec<String>,
+    /// File name of the output with `{name}` replaced, defaults to the platform's naming.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    /// Added when the profile sets `optimize`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub optimize: Vec<String>,
+    /// Added when the profile sets `debug_info`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub debug_info: Vec<String>,
+    /// Added when building for another triple, with `{triple}` replaced.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cross: Vec<String>
+}
+
+/// How to build for a target triple other than the host.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Toolchain {
+    /// Put before compiler names looked up on `PATH`, and before `ar`, e.g. `aarch64-linux-gnu-`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+    /// Passed to C and C++ compilers as `--sysroot`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sysroot: Option<String>,
+    /// Added to every compiler command for this triple.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub flags: Vec<String>,
+    /// Set for every compiler command for this triple.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct CacheConfig {
+    /// A directory, or an `http://` or `https://` URL answering `GET` and `PUT`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote: Option<String>,
+    #[serde(default)]
+    pub mode: CacheMode
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, Default, PartialEq, Eq, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum CacheMode {
+    /// Uses what others stored without adding to it.
+    #[default]
+    ReadOnly,
+    /// Also stores what this machine builds.
+    ReadWrite
+}
+
+impl CacheMode {
+    pub fn from_name(name: &str) -> Option<CacheMode> {
+        match name {
+            "read-only" => Some(CacheMode::ReadOnly),
+            "read-write" => Some(CacheMode::ReadWrite),
+            _ => None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CacheMode::ReadOnly => "read-only",
+            CacheMode::ReadWrite => "read-write"
+        }
+    }
+}
+
+/// A hook listed in the configuration, by name alone when it runs in the phase its header sets.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq, Debug)]
+#[serde(untagged)]
+pub enum HookEntry {
+    Name(String),
+    Phased(PhasedHook)
+}
+
+impl HookEntry {
+    pub fn name(&self) -> &str {
+        match self {
+            HookEntry::Name(name) => name,
+            HookEntry::Phased(hook) => &hook.name
+        }
+    }
+
+    /// The phase set here, which takes precedence over the one in the hook's header.
+    pub fn phase(&self) -> Option<HookPhase> {
+        match self {
+            HookEntry::Name(_) => None,
+            HookEntry::Phased(hook) => hook.phase
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct PhasedHook {
+    /// A hook name or `.cly` path.
+    pub name: String,
+    /// Defaults to the `phase` in the hook's header, then to `pre-build`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub phase: Option<HookPhase>
+}
+
+/// When a hook runs, in the order phases run.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum HookPhase {
+    /// Before targets are compiled, e.g. to generate sources.
+    #[default]
+    PreBuild,
+    /// Alongside the targets, for projects whose hooks do the building.
+    Build,
+    /// After every target was built.
+    PostBuild,
+    /// With `cly package`, after the build.
+    Package,
+    /// With `cly clean`, before artifacts are removed.
+    Clean
+}
+
+impl HookPhase {
+    pub fn name(&self) -> &'static str {
+        match self {
+            HookPhase::PreBuild => "pre-build",
+            HookPhase::Build => "build",
+            HookPhase::PostBuild => "post-build",
+            HookPhase::Package => "package",
+            HookPhase::Clean => "clean"
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Workspace {
+    pub members: Vec<Member>
+}
+
+/// A project of the workspace, known by the `name` in its own config.
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Member {
+    /// Directory holding the member's `.catalyst/`, relative to the workspace root.
+    pub path: String,
+    /// Names of the members built before this one.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<String>
 }
\ No newline at end of file
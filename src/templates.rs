@@ -0,0 +1,116 @@
+use std::{collections::BTreeSet, path::{Path, PathBuf}};
+
+use crate::{compiler::Registry, structs::{OutputKind, Target}};
+
+/// Starting points for `cly init`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Template {
+    CApp,
+    RustBin,
+    Node,
+    Empty
+}
+
+impl Template {
+    pub const NAMES: [&'static str; 4] = ["c-app", "rust-bin", "node", "empty"];
+
+    pub fn from_name(name: &str) -> Option<Template> {
+        match name {
+            "c-app" => Some(Template::CApp),
+            "rust-bin" => Some(Template::RustBin),
+            "node" => Some(Template::Node),
+            "empty" => Some(Template::Empty),
+            _ => None
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Template::CApp => "c-app",
+            Template::RustBin => "rust-bin",
+            Template::Node => "node",
+            Template::Empty => "empty"
+        }
+    }
+
+    /// The template for a project mostly written in `language`.
+    pub fn for_language(language: &str) -> Template {
+        match language {
+            "C" | "C++" => Template::CApp,
+            "Rust" => Template::RustBin,
+            "JavaScript" | "TypeScript" => Template::Node,
+            _ => Template::Empty
+        }
+    }
+
+    /// The language of the template's starter target.
+    pub fn language(self) -> Option<&'static str> {
+        match self {
+            Template::CApp => Some("C"),
+            Template::RustBin => Some("Rust"),
+            Template::Node | Template::Empty => None
+        }
+    }
+
+    /// A source file to start from, written when the directory has none in the template's language.
+    pub fn starter(self) -> Option<(&'static str, &'static str)> {
+        match self {
+            Template::CApp => Some(("src/main.c", "#include <stdio.h>\n\nint main(void) {\n    printf(\"Hello, world!\\n\");\n    return 0;\n}\n")),
+            Template::RustBin => Some(("src/main.rs", "fn main() {\n    println!(\"Hello, world!\");\n}\n")),
+            Template::Node | Template::Empty => None
+        }
+    }
+
+    /// The content of `.catalyst/hooks/main.cly`.
+    pub fn hook(self) -> &'static str {
+        match self {
+            Template::Node => "---\nlanguage: lua\ndescription: Installs the dependencies and runs the build script\nphase: pre-build\n---\n-- Runs with `cly`. Installs the dependencies and runs the build script, if any.\nif fs.exists(\"package.json\") then\n    shell(\"sh\", \"npm install\")\n    shell(\"sh\", \"npm run build --if-present\")\nend\n",
+            _ => "---\nlanguage: lua\ndescription: Logs the profile the build runs with\nphase: pre-build\n---\n-- Runs with `cly`. Use `cly build` to compile the targets.\nlog.info(\"Running the main hook of \" .. fs.getcwd() .. \" with profile \" .. catalyst.profile)\n"
+        }
+    }
+}
+
+const HEADERS: [&str; 4] = ["h", "hh", "hpp", "hxx"];
+
+/// A target building the detected files of `language`, if a built-in driver compiles it.
+pub fn target(name: &str, language: &str, files: &[PathBuf]) -> Option<Target> {
+    let sources = match language {
+        // rustc compiles a single crate root and finds the modules itself.
+        "Rust" => {
+            let root = files.iter()
+                .find(|file| file.ends_with("main.rs"))
+                .or(files.iter().find(|file| file.ends_with("lib.rs")))
+                .or(files.first())?;
+            vec![slashed(root)]
+        }
+        language if Registry::builtin().get(language).is_some() => files.iter()
+            .filter_map(|file| {
+                let extension = file.extension()?.to_string_lossy();
+                if HEADERS.contains(&extension.as_ref()) {
+                    return None
+                }
+                let dir = slashed(file.parent()?);
+                Some(if dir.is_empty() { format!("*.{}", extension) } else { format!("{}/*.{}", dir, extension) })
+            })
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect(),
+        _ => return None
+    };
+
+    Some(Target {
+        name: name.to_string(),
+        sources,
+        language: Some(language.to_string()),
+        compiler: None,
+        flags: Vec::new(),
+        kind: OutputKind::Executable,
+        output: None,
+        inputs: Vec::new(),
+        hermetic: false
+    })
+}
+
+fn slashed(path: &Path) -> String {
+    path.strip_prefix(".").unwrap_or(path).to_string_lossy().replace('\\', "/")
+}
@@ -82,5 +82,5 @@ pub fn check(ver: &str) -> bool {
         return true;
     }
 
-    return false;
+    false
 }
\ No newline at end of file
@@ -1,21 +1,68 @@
-use std::{fs::{self, File}, io::{self, BufReader, BufWriter, Error, Read, Write}, path::Path, process::Command as Cmd, vec};
+use std::{env::{self, consts}, fs::{self, File}, io::{self, BufReader, BufWriter, Error, IsTerminal, Read, Write}, path::{Component, Path, PathBuf}, process::Command as Cmd, vec};
 use anstyle::{AnsiColor, Color, Style};
 use clap::{arg, builder::Styles, command, value_parser, ArgAction, ArgMatches, Command};
 use hex_rgb::{convert_hexcode_to_rgb, Color as rgbcolor};
 use hyperpolyglot::{get_language_breakdown, Language};
-use log::info;
-use serde_json::to_string_pretty;
-use sysinfo::System;
+use log::{error, info};
+use sysinfo::{CpuRefreshKind, RefreshKind, System};
 use walkdir::WalkDir;
 use owo_colors::{OwoColorize, Stream};
 use zip::{write::FullFileOptions, ZipArchive, ZipWriter};
 use dialoguer::Input;
 
-use crate::{structs, CATALYST_VERSION};
+use crate::{config::{self, Format, CONFIG_DIR}, hooks::HOOKS_DIR, structs, templates::{self, Template}, CATALYST_VERSION};
+
+/// Where Catalyst keeps its own files: `~/.catalyst`, or the temp directory on Windows.
+pub fn catalyst_dir() -> PathBuf {
+    if consts::OS == "windows" {
+        return PathBuf::from(format!("C:\\Users\\{}\\AppData\\Local\\Temp\\Catalyst", whoami::username()))
+    }
+    let home = env::var_os("HOME").map(PathBuf::from).unwrap_or(PathBuf::from(format!("/home/{}", whoami::username())));
+    home.join(".catalyst")
+}
+
+pub fn cache_dir() -> PathBuf {
+    catalyst_dir().join("cache")
+}
+
+/// Where the logger writes: `~/.catalyst/logs`, or straight into the Catalyst temp directory on Windows.
+pub fn logs_dir() -> PathBuf {
+    if consts::OS == "windows" {
+        return catalyst_dir()
+    }
+    catalyst_dir().join("logs")
+}
+
+/// Whether `path` is relative and stays inside the directory it is relative to.
+pub fn contained(path: &Path) -> bool {
+    path.components().all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+/// Folds `.` and `..` away without touching the filesystem.
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if normalized.file_name().is_some() => { normalized.pop(); }
+            component => normalized.push(component)
+        }
+    }
+    normalized
+}
+
+pub fn prompt_default(msg: String, default: String) -> String {
+    Input::new()
+        .with_prompt(msg)
+        .default(default)
+        .interact_text()
+        .expect("Failed to read input.")
+}
 
 pub fn prompt(msg: String) -> Option<String> {
     let res: String = Input::new()
         .with_prompt(msg)
+        .allow_empty(true)
         .interact_text()
         .expect("Failed to read input.");
 
@@ -26,106 +73,148 @@ pub fn prompt(msg: String) -> Option<String> {
     }
 }
 
+/// Finds the least nested file whose name contains one of `file_name`, alphabetically
+/// first among equally nested ones.
 pub fn find_file<P: AsRef<Path>>(dir: P, file_name: Vec<&str>) -> Result<std::path::PathBuf, Error> {
-    for entry in WalkDir::new(dir) {
-        if let Ok(entry) = entry {
-            for file in file_name.clone() {
-                if entry.file_name().to_string_lossy().to_string().contains(file) {
-                    return Ok(entry.path().to_path_buf())
-                }
-            }
-        }
-    }
-    Err(Error::new(io::ErrorKind::NotFound, "File not found"))
+    WalkDir::new(dir)
+        .sort_by_file_name()
+        .into_iter()
+        .flatten()
+        .filter(|entry| file_name.iter().any(|file| entry.file_name().to_string_lossy().contains(file)))
+        .min_by_key(|entry| entry.depth())
+        .map(|entry| entry.path().to_path_buf())
+        .ok_or(Error::new(io::ErrorKind::NotFound, "File not found"))
 }
 
-pub fn generate() -> bool {
-    if Path::new("./config.cly").exists() {
-        return false
+/// Writes `.catalyst/config.cly.<format>` and a starter hook. Asks for what was not given on
+/// the command line, unless `--yes` is set or there is no terminal to ask on.
+pub fn generate(args: &ArgMatches) -> bool {
+    let format = args.get_one::<String>("format").and_then(|format| Format::from_name(format)).unwrap_or(Format::Json);
+    let destination = Path::new(CONFIG_DIR).join(format.file_name());
+    let existing = config::find_in(".");
+    if let Some(existing) = &existing {
+        if !args.get_flag("force") {
+            println!("{}", format!("{} already exists, use --force to replace it.", existing.display()).if_supports_color(Stream::Stdout, |text| text.red()));
+            return false
+        }
     }
 
-    let mut config: structs::Config = structs::Config {
-        name: String::new(),
-        version: None,
-        working_directory: String::new(),
-        hooks: Vec::new()
-    };
+    let interactive = !args.get_flag("yes") && io::stdin().is_terminal();
+    let directory = env::current_dir().ok()
+        .and_then(|dir| dir.file_name().map(|name| name.to_string_lossy().to_string()))
+        .unwrap_or("project".to_string());
 
-    let input = prompt("Enter project name: ".to_string());
-    if let Some(input) = input {
-        config.name = input;
-    }
-    else {
+    let name = match args.get_one::<String>("name") {
+        Some(name) => name.clone(),
+        None if interactive => prompt_default("Enter project name".to_string(), directory),
+        None => directory
+    };
+    let name = name.trim().to_string();
+    if name.is_empty() {
         println!("{}", "Project name cannot be empty.".if_supports_color(Stream::Stdout, |text| text.red()).if_supports_color(Stream::Stdout, |text| text.bold()));
         return false
     }
 
-    config.name = config.name.trim().to_string();
+    let version = match args.get_one::<String>("version") {
+        Some(version) => Some(version.clone()),
+        None if interactive => prompt("Enter project version (can be left blank)".to_string()),
+        None => None
+    };
 
-    let _input = prompt("Enter project version (can be left blank): ".to_string());
-    if let Some(input) = _input {
-        config.version = Some(input);
-    }
-    else {
-        config.version = None;
+    let mut languages = detect_languages();
+    let template = match args.get_one::<String>("template") {
+        Some(template) => Template::from_name(template).unwrap(),
+        None => languages.first().map(|(language, _)| Template::for_language(language)).unwrap_or(Template::Empty)
+    };
+    info!("Using template: {}", template.name());
+
+    if let (Some(language), Some((path, content))) = (template.language(), template.starter()) {
+        if !languages.iter().any(|(detected, _)| detected == language) {
+            if !Path::new(path).exists() {
+                let written = Path::new(path).parent().map_or(Ok(()), fs::create_dir_all).and_then(|_| fs::write(path, content));
+                if let Err(err) = written {
+                    error!("Cannot write {}: {}", path, err);
+                    return false
+                }
+                println!("{}", format!("Wrote {}", path).if_supports_color(Stream::Stdout, |text| text.green()));
+            }
+            languages.insert(0, (language.to_string(), vec![PathBuf::from(path)]));
+        }
     }
 
-    let _input = prompt("Enter working directory (can be left blank): ".to_string());
-    if let Some(input) = _input {
-        config.working_directory = input;
+    let mut targets: Vec<structs::Target> = Vec::new();
+    for (language, files) in &languages {
+        let target = if targets.is_empty() { name.clone() } else { format!("{}-{}", name, language.to_lowercase()) };
+        targets.extend(templates::target(&target, language, files));
     }
 
-        config.hooks = vec!["main".to_string()];
-
-    config.name = config.name.trim().to_string();
+    let config = structs::Config {
+        name,
+        version,
+        hooks: vec![structs::HookEntry::Name("main".to_string())],
+        targets,
+        ..Default::default()
+    };
+    let content = match serde_json::to_value(&config).map_err(|err| err.to_string()).and_then(|value| format.serialize(&value)) {
+        Ok(content) => content,
+        Err(err) => {
+            error!("{}", err);
+            return false
+        }
+    };
 
-    let config_json = to_string_pretty(&config).unwrap();
-    fs::DirBuilder::new().create(".catalyst").unwrap();
-    let _ = fs::write(".catalyst/config.cly.json", config_json);
+    let hook = Path::new(CONFIG_DIR).join(HOOKS_DIR).join("main.cly");
+    let written = fs::create_dir_all(Path::new(CONFIG_DIR).join(HOOKS_DIR))
+        .and_then(|_| fs::write(&destination, content))
+        .and_then(|_| if hook.exists() { Ok(()) } else { fs::write(&hook, template.hook()) });
+    if let Err(err) = written {
+        error!("Cannot write the configuration: {}", err);
+        return false
+    }
+    // Another format would take precedence over, or be shadowed by, the new file.
+    if let Some(existing) = existing.filter(|existing| !existing.ends_with(&destination)) {
+        let _ = fs::remove_file(existing);
+    }
 
+    println!("{}", format!("Created {} from the {} template.", destination.display(), template.name()).if_supports_color(Stream::Stdout, |text| text.green()));
+    for target in &config.targets {
+        println!("  target {}: {}", target.name, target.sources.join(", "));
+    }
     true
 }
 
-#[allow(dead_code)]
-pub fn detect_languages() -> Vec<String> {
-    
-    let mut languages: Vec<String> = Vec::new();
-
-    info!("Scanning current directory...");  
+/// The languages found in the current directory with their files, most used first.
+pub fn detect_languages() -> Vec<(String, Vec<PathBuf>)> {
+    info!("Scanning current directory...");
 
     let breakdown = get_language_breakdown("./");
+    if breakdown.is_empty() {
+        return Vec::new()
+    }
     let mut total_files = 0;
-    for (_language, detections) in &breakdown {
+    for detections in breakdown.values() {
         total_files += detections.len();
     }
 
-    let percentage = breakdown.iter().map(|(language, detections)| {
-        languages.push(language.to_string());
-        format!("{}: {}%", language, ((detections.len() as f64 / total_files as f64) * 100.0).round().to_string())
-    }).collect::<Vec<_>>().join(", ");
-    
-    println!("{}", format!("Languages used:").to_string().if_supports_color(Stream::Stdout, |text| text.blue()));
-        for lang in percentage.split(", ") {
-            let language_struct = Language::try_from(lang.split(":").next().unwrap()).unwrap();
-            let hex_color = language_struct.color;
-            match hex_color {
-                Some(hex_color) => {
-                    let color: rgbcolor = convert_hexcode_to_rgb(hex_color.to_string()).unwrap();
-                    print!("{}\n", format!("{}", lang.to_string()).to_string().if_supports_color(Stream::Stdout, |text| text.truecolor(color.red, color.green, color.blue)));
-                }
-                None => {
-                    print!("{}\n", format!("{}", lang.to_string()).to_string().if_supports_color(Stream::Stdout, |text| text.truecolor(255, 255, 255)));
-                }
+    let mut languages = breakdown.into_iter()
+        .map(|(language, detections)| (language.to_string(), detections.into_iter().map(|(_, path)| path).collect::<Vec<_>>()))
+        .collect::<Vec<_>>();
+    languages.sort_by(|a, b| b.1.len().cmp(&a.1.len()).then(a.0.cmp(&b.0)));
+
+    println!("{}", "Languages used:".if_supports_color(Stream::Stdout, |text| text.blue()));
+    for (language, files) in &languages {
+        let line = format!("{}: {}%", language, ((files.len() as f64 / total_files as f64) * 100.0).round());
+        match Language::try_from(language.as_str()).ok().and_then(|language| language.color) {
+            Some(hex_color) => {
+                let color: rgbcolor = convert_hexcode_to_rgb(hex_color.to_string()).unwrap();
+                println!("{}", line.if_supports_color(Stream::Stdout, |text| text.truecolor(color.red, color.green, color.blue)));
+            }
+            None => {
+                println!("{}", line.if_supports_color(Stream::Stdout, |text| text.truecolor(255, 255, 255)));
             }
-            
         }
-
-    if languages.len() > 0 {
-        let languages = languages.join(", ");
-        println!("{}", format!("Total files: {}", total_files).to_string().if_supports_color(Stream::Stdout, |text| text.blue()));
-        println!("{}", format!("Languages detected: {}", languages).to_string().if_supports_color(Stream::Stdout, |text| text.blue()));   
-        println!("{}", format!("Done.").to_string().if_supports_color(Stream::Stdout, |text| text.blue()));
     }
+    println!("{}", format!("Total files: {}", total_files).if_supports_color(Stream::Stdout, |text| text.blue()));
     languages
 }
 
@@ -152,11 +241,39 @@ pub fn args() -> ArgMatches {
         .styles(styles)
         .version(CATALYST_VERSION)
         .arg(
-            arg!(-c --config <FILE> "Configuration file to use, default: .catalyst/config.cly.json")
+            arg!(-c --config <FILE> "Configuration file to use, default: .catalyst/config.cly.{json,toml,yaml}")
+                .required(false)
+                .action(ArgAction::Set)
+                .value_parser(value_parser!(String)),
+        )
+        .arg(
+            arg!(-p --profile <PROFILE> "Build profile to use, default: debug")
+                .required(false)
+                .global(true)
+                .action(ArgAction::Set)
+                .value_parser(value_parser!(String)),
+        )
+        .arg(
+            arg!(--package <NAME> "Workspace member to run on, default: the current one, or all at the root")
                 .required(false)
+                .global(true)
                 .action(ArgAction::Set)
                 .value_parser(value_parser!(String)),
         )
+        .arg(
+            arg!(-j --jobs <N> "Number of actions run at once, default: the number of CPU cores")
+                .required(false)
+                .global(true)
+                .action(ArgAction::Set)
+                .value_parser(value_parser!(usize)),
+        )
+        .arg(
+            arg!(--keep_going "Keeps running what does not depend on a failed action")
+                .long("keep-going")
+                .required(false)
+                .global(true)
+                .action(ArgAction::SetTrue),
+        )
         .arg(
             arg!(-n --nologs "Disables logging")
                 .action(ArgAction::SetTrue)
@@ -178,20 +295,161 @@ pub fn args() -> ArgMatches {
                 .action(ArgAction::SetTrue)
                 .required(false),
         )
-        .subcommand(Command::new("init").about("Initializes a new configuration file"))
-        .subcommand(Command::new("cleanup").about("Cleans up the logs."))
+        .arg(arg!([args] ... "Arguments for the hook run with -H, after `--`").last(true))
+        .subcommand(
+            Command::new("init")
+                .about("Initializes a new configuration file")
+                .arg(arg!(--name <NAME> "Project name, default: the directory name"))
+                .arg(arg!(--version <VERSION> "Project version"))
+                .arg(
+                    arg!(--template <TEMPLATE> "Template to start from, default: from the detected languages")
+                        .value_parser(Template::NAMES)
+                )
+                .arg(
+                    arg!(--format <FORMAT> "Format of the configuration file, default: json")
+                        .value_parser(["json", "toml", "yaml"])
+                )
+                .arg(arg!(-y --yes "Uses the defaults instead of asking").action(ArgAction::SetTrue))
+                .arg(arg!(--force "Replaces an existing configuration file").action(ArgAction::SetTrue))
+        )
+        .subcommand(build_args(
+            Command::new("build")
+                .about("Runs the pre-build hooks, compiles the targets declared in the configuration and runs the post-build hooks.")
+        ))
+        .subcommand(build_args(
+            Command::new("package")
+                .about("Builds like `cly build`, then runs the package hooks.")
+        ))
+        .subcommand(
+            Command::new("compdb")
+                .about("Writes compile_commands.json for editors, without building.")
+        )
+        .subcommand(
+            Command::new("config")
+                .about("Works with the configuration file.")
+                .subcommand_required(true)
+                .subcommand(Command::new("validate").about("Checks the configuration file and reports every problem."))
+                .subcommand(Command::new("schema").about("Prints the JSON Schema of the configuration file."))
+                .subcommand(
+                    Command::new("show")
+                        .about("Prints the configuration file.")
+                        .arg(
+                            arg!(--resolved "Prints it with its bases merged in and its variables expanded")
+                                .action(ArgAction::SetTrue)
+                        )
+                )
+                .subcommand(
+                    Command::new("get")
+                        .about("Prints the value at a key path such as targets.app.flags or targets[0].name.")
+                        .arg(arg!(<key> "Key path"))
+                        .arg(
+                            arg!(--resolved "Reads the config with its bases merged in and its variables expanded")
+                                .action(ArgAction::SetTrue)
+                        )
+                )
+                .subcommand(
+                    Command::new("set")
+                        .about("Sets the value at a key path. Values the schema does not accept as a string are read as JSON.")
+                        .arg(arg!(<key> "Key path"))
+                        .arg(arg!(<value> "Value to set").allow_hyphen_values(true))
+                )
+                .subcommand(
+                    Command::new("unset")
+                        .about("Removes the value at a key path.")
+                        .arg(arg!(<key> "Key path"))
+                )
+                .subcommand(
+                    Command::new("list")
+                        .about("Prints every value of the configuration file with its key path.")
+                        .arg(
+                            arg!(--resolved "Reads the config with its bases merged in and its variables expanded")
+                                .action(ArgAction::SetTrue)
+                        )
+                )
+                .subcommand(
+                    Command::new("convert")
+                        .about("Converts the configuration file to another format.")
+                        .arg(
+                            arg!(--to <FORMAT> "Format to convert to")
+                                .required(true)
+                                .value_parser(["json", "toml", "yaml"])
+                        )
+                        .arg(
+                            arg!(--keep "Keeps the original file")
+                                .action(ArgAction::SetTrue)
+                        )
+                        .arg(
+                            arg!(--force "Overwrites a config file already in that format")
+                                .action(ArgAction::SetTrue)
+                        )
+                )
+        )
+        .subcommand(
+            Command::new("run")
+                .about("Runs a hook of the project, after the hooks it depends on.")
+                .arg(arg!(<hook> "Hook name or .cly path"))
+                .arg(arg!(--set <PARAM> "Sets a parameter of the hook as name=value, repeatable").action(ArgAction::Append).value_parser(parse_param))
+                .arg(arg!([args] ... "Arguments for the hook, after `--`").last(true))
+        )
+        .subcommand(
+            Command::new("hooks")
+                .about("Works with the hooks of the project.")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("list")
+                        .about("Lists the hooks found in the hook directories with their language, phase and description.")
+                        .arg(arg!(--json "Prints the list as JSON").action(ArgAction::SetTrue))
+                )
+        )
+        .subcommand(
+            Command::new("clean")
+                .visible_alias("cleanup")
+                .about("Runs the clean hooks and removes build artifacts, and the cache or logs when asked. Limited to the active profile with --profile.")
+                .arg(arg!(triple: --target <TRIPLE> "Only removes the artifacts of a target triple, `host` for host builds"))
+                .arg(arg!(--cache "Also removes the build cache").action(ArgAction::SetTrue))
+                .arg(arg!(--logs "Also removes the logs").action(ArgAction::SetTrue))
+                .arg(arg!(dry_run: --"dry-run" "Lists what would be removed without removing it").action(ArgAction::SetTrue))
+        )
         .subcommand(Command::new("update").about("Updates the catalyst application."))
         .subcommand(Command::new("check").about("Checks for updates."));
 
     cmd.get_matches()
 }
 
+fn parse_param(text: &str) -> Result<(String, String), String> {
+    match text.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((name.to_string(), value.to_string())),
+        _ => Err("expected name=value".to_string())
+    }
+}
+
+/// The arguments `build` and `package` share.
+fn build_args(command: Command) -> Command {
+    command
+        .arg(arg!([target] "Target to build, default: all targets"))
+        .arg(arg!(--force "Rebuilds even what is up to date").action(ArgAction::SetTrue))
+        .arg(arg!(triple: --target <TRIPLE> "Cross-compiles for a target triple, repeatable, default: `matrix` or the host").action(ArgAction::Append))
+        .arg(arg!(--hermetic "Builds every target hermetically, as if it set `hermetic`").action(ArgAction::SetTrue))
+        .arg(arg!(--compdb "Also writes compile_commands.json for C and C++ sources").action(ArgAction::SetTrue))
+}
+
 pub fn is_tool(tool: &str) -> bool {
     let output = Cmd::new(tool).output().unwrap();
     output.status.success()
 }
 
+pub fn cpu_count() -> usize {
+    System::new_with_specifics(RefreshKind::new().with_cpu(CpuRefreshKind::new())).cpus().len()
+}
+
 pub fn banner(matches: ArgMatches) {
+    // Commands whose output is meant to be piped stay silent.
+    if let Some((command, sub)) = matches.subcommand() {
+        match (command, sub.subcommand_name()) {
+            ("config", Some("schema" | "show" | "get" | "list")) | ("hooks", Some("list")) => return,
+            _ => {}
+        }
+    }
     let sys = System::new_all();
     let debugmode = matches.get_flag("debug");
     let verbose = matches.get_flag("verbose");
@@ -199,7 +457,7 @@ pub fn banner(matches: ArgMatches) {
     let debugstatus = if debugmode { "Debug mode enabled.".yellow() } else { "".yellow()};
     let platform = format!("Platform: {}", System::name().unwrap().purple());
     let arch = format!("Architecture: {}", System::cpu_arch().unwrap().to_string().purple());
-    let cores = format!("CPU cores: {}", sys.cpus().len().to_string().purple());
+    let cores = format!("CPU cores: {}", cpu_count().to_string().purple());
     let mem = format!("Memory: {} GB", ((sys.total_memory() / 1024 / 1024 /1024) + 1).to_string().purple());
     let version = CATALYST_VERSION.purple();
     let sysinfo = format!(
@@ -233,7 +491,7 @@ pub fn banner(matches: ArgMatches) {
 
 pub fn extract_zip(file: String, dest: String) -> zip::result::ZipResult<()> {
     let path = std::path::Path::new(file.as_str());
-    let file = File::open(&path)?;
+    let file = File::open(path)?;
     let mut archive = ZipArchive::new(BufReader::new(file))?;
 
     for i in 0..archive.len() {
@@ -241,14 +499,14 @@ pub fn extract_zip(file: String, dest: String) -> zip::result::ZipResult<()> {
         let outpath = std::path::Path::new(dest.as_str());
 
         if file.name().ends_with('/') {
-            std::fs::create_dir_all(&outpath)?;
+            std::fs::create_dir_all(outpath)?;
         } else {
             if let Some(p) = outpath.parent() {
                 if !p.exists() {
-                    std::fs::create_dir_all(&p)?;
+                    std::fs::create_dir_all(p)?;
                 }
             }
-            let mut outfile = File::create(&outpath)?;
+            let mut outfile = File::create(outpath)?;
             std::io::copy(&mut file, &mut outfile)?;
         }
     }
@@ -257,16 +515,16 @@ pub fn extract_zip(file: String, dest: String) -> zip::result::ZipResult<()> {
 
 pub fn package_zip(file_paths: Vec<String>, zip_path: &str) -> zip::result::ZipResult<()> {
     let path = Path::new(zip_path);
-    let file = File::create(&path)?;
+    let file = File::create(path)?;
     let mut zip = ZipWriter::new(BufWriter::new(file));
     let options = FullFileOptions::default().compression_method(zip::CompressionMethod::Stored);
 
     for file_path in file_paths {
         let path = Path::new(file_path.as_str());
-        let mut f = File::open(&path)?;
+        let mut f = File::open(path)?;
         let mut buffer = Vec::new();
         f.read_to_end(&mut buffer)?;
-        let _ = zip.start_file(
+        zip.start_file(
             path.file_name().unwrap().to_str().unwrap(),
             options.clone()
         )?;
@@ -0,0 +1,688 @@
+use std::{fmt, path::Path};
+use hyperpolyglot::Language;
+use owo_colors::{OwoColorize, Stream::Stdout};
+use schemars::{schema::{InstanceType, RootSchema, Schema, SchemaObject, SingleOrVec}, schema_for};
+use serde_json::Value;
+use toml_edit::{ImDocument, Item, TableLike};
+
+use crate::{config::{self, Format}, extends, hooks, interpolate, structs::Config};
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Segment {
+    Key(String),
+    Index(usize)
+}
+
+/// Renders a key path the way users write it, e.g. `targets[0].kind`.
+pub struct KeyPath<'a>(pub &'a [Segment]);
+
+impl fmt::Display for KeyPath<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            return write!(f, "<root>")
+        }
+        for (i, segment) in self.0.iter().enumerate() {
+            match segment {
+                Segment::Key(key) if i == 0 => write!(f, "{}", key)?,
+                Segment::Key(key) => write!(f, ".{}", key)?,
+                Segment::Index(index) => write!(f, "[{}]", index)?
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct Problem {
+    pub path: Vec<Segment>,
+    pub message: String,
+    pub hint: Option<String>,
+    pub line: usize,
+    pub column: usize
+}
+
+impl Problem {
+    pub fn new(path: &[Segment], message: String, hint: Option<String>) -> Problem {
+        Problem { path: path.to_vec(), message, hint, line: 0, column: 0 }
+    }
+}
+
+pub fn schema() -> RootSchema {
+    schema_for!(Config)
+}
+
+/// Parses, interpolates and checks a config file, collecting every problem instead of
+/// stopping at the first.
+pub fn validate(content: &str, format: Format, file: &Path, root: &Path, profile: &str) -> Result<Config, Vec<Problem>> {
+    let value = resolve(content, format, file, root, profile)?;
+    serde_json::from_value::<Config>(value).map_err(|err| located(content, format, vec![Problem::new(&[], err.to_string(), None)]))
+}
+
+/// The checked value of a config file, with its bases merged in and its variables expanded.
+pub fn resolve(content: &str, format: Format, file: &Path, root: &Path, profile: &str) -> Result<Value, Vec<Problem>> {
+    let value = parse(content, format).map_err(|problem| vec![problem])?;
+    let mut value = extends::resolve(value, file).map_err(|problems| located(content, format, problems))?;
+
+    let mut problems = interpolate::expand(&mut value, root, profile);
+    problems.extend(check(&value));
+    problems.extend(semantics(&value, root));
+    if !problems.is_empty() {
+        return Err(located(content, format, problems))
+    }
+    Ok(value)
+}
+
+/// Fills in where each problem is in the file and sorts them by it. Problems in keys that
+/// come from a base config point at the closest key of the file itself.
+fn located(content: &str, format: Format, mut problems: Vec<Problem>) -> Vec<Problem> {
+    for problem in problems.iter_mut() {
+        let offset = locate(content, format, &problem.path);
+        (problem.line, problem.column) = line_column(content, offset);
+    }
+    problems.sort_by_key(|problem| (problem.line, problem.column));
+    problems
+}
+
+pub fn report(file: &Path, problems: &[Problem]) {
+    for problem in problems {
+        println!(
+            "{}:{}:{}: {} {}",
+            file.display(),
+            problem.line,
+            problem.column,
+            "error:".if_supports_color(Stdout, |text| text.red()),
+            problem.message
+        );
+        if let Some(hint) = &problem.hint {
+            println!("  {} {}", "hint:".if_supports_color(Stdout, |text| text.cyan()), hint);
+        }
+    }
+    println!("{}", format!("{} problem(s) found.", problems.len()).if_supports_color(Stdout, |text| text.red()));
+}
+
+fn parse(content: &str, format: Format) -> Result<Value, Problem> {
+    let (message, offset) = match format {
+        Format::Json => match serde_json::from_str(content) {
+            Ok(value) => return Ok(value),
+            Err(err) => {
+                let message = err.to_string();
+                let message = message.split(" at line").next().unwrap_or_default().to_string();
+                return Err(Problem { path: Vec::new(), message, hint: None, line: err.line(), column: err.column() })
+            }
+        },
+        Format::Toml => match toml::from_str(content) {
+            Ok(value) => return Ok(value),
+            Err(err) => (err.message().to_string(), err.span().map(|span| span.start).unwrap_or(0))
+        },
+        Format::Yaml => match serde_yaml::from_str(content) {
+            Ok(value) => return Ok(value),
+            Err(err) => (err.to_string(), err.location().map(|location| location.index()).unwrap_or(0))
+        }
+    };
+    let (line, column) = line_column(content, offset);
+    Err(Problem { path: Vec::new(), message, hint: None, line, column })
+}
+
+/// Checks a parsed config against the schema generated from [`Config`].
+pub fn check(value: &Value) -> Vec<Problem> {
+    let root = schema();
+    let mut checker = Checker { root: &root, problems: Vec::new() };
+    checker.check_object(&root.schema, value, &mut Vec::new());
+    checker.problems
+}
+
+/// Checks what the schema cannot express. Works on the raw value so that it runs even when
+/// the schema check fails, skipping anything that does not have the expected shape.
+fn semantics(value: &Value, root: &Path) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let key = |key: &str| Segment::Key(key.to_string());
+
+    let working_directory = value.get("working_directory").and_then(Value::as_str).unwrap_or_default();
+    let working_directory = root.join(working_directory);
+    if !working_directory.is_dir() {
+        problems.push(Problem::new(
+            &[key("working_directory")],
+            format!("working directory {} does not exist", working_directory.display()),
+            Some("the path is relative to the directory containing .catalyst/".to_string())
+        ));
+    }
+
+    let hook_dirs = value.get("hook_dirs").and_then(Value::as_array).into_iter().flatten().filter_map(Value::as_str).map(String::from).collect::<Vec<_>>();
+    let hook_names = value.get("hooks").and_then(Value::as_array).into_iter().flatten();
+    for (i, hook) in hook_names.enumerate() {
+        if let Some(hook) = hook_name(hook) {
+            match hooks::resolve(root, &hook_dirs, hook).map(|path| hooks::Hook::load(&path)) {
+                None => problems.push(Problem::new(
+                    &[key("hooks"), Segment::Index(i)],
+                    format!("hook `{}` does not exist", hook),
+                    Some(format!("create .catalyst/hooks/{}.cly or remove it from the list", hook))
+                )),
+                Some(Err(err)) => problems.push(Problem::new(&[key("hooks"), Segment::Index(i)], format!("invalid hook {}", err), None)),
+                Some(Ok(_)) => {}
+            }
+        }
+    }
+
+    if let Some(profiles) = value.get("profiles").and_then(Value::as_object) {
+        for (name, profile) in profiles {
+            if let Some(parent) = profile.get("inherits").and_then(Value::as_str) {
+                if !profiles.contains_key(parent) && !["debug", "release"].contains(&parent) {
+                    problems.push(Problem::new(
+                        &[key("profiles"), key(name), key("inherits")],
+                        format!("profile `{}` inherits from unknown profile `{}`", name, parent),
+                        None
+                    ));
+                }
+            }
+            let hook_names = profile.get("hooks").and_then(Value::as_array).into_iter().flatten();
+            for (i, hook) in hook_names.enumerate() {
+                if let Some(hook) = hook_name(hook) {
+                    let path = [key("profiles"), key(name), key("hooks"), Segment::Index(i)];
+                    match hooks::resolve(root, &hook_dirs, hook).map(|path| hooks::Hook::load(&path)) {
+                        None => problems.push(Problem::new(&path, format!("hook `{}` does not exist", hook), None)),
+                        Some(Err(err)) => problems.push(Problem::new(&path, format!("invalid hook {}", err), None)),
+                        Some(Ok(_)) => {}
+                    }
+                }
+            }
+        }
+    }
+
+    let members = value.get("workspace").and_then(|workspace| workspace.get("members")).and_then(Value::as_array).into_iter().flatten();
+    for (i, member) in members.enumerate() {
+        if let Some(path) = member.get("path").and_then(Value::as_str) {
+            if config::find_in(root.join(path)).is_none() {
+                problems.push(Problem::new(
+                    &[key("workspace"), key("members"), Segment::Index(i), key("path")],
+                    format!("workspace member {} has no configuration file", path),
+                    Some(format!("run `cly init` in {}", path))
+                ));
+            }
+        }
+    }
+
+    let mut languages = Vec::new();
+    let drivers = value.get("drivers").and_then(Value::as_array).into_iter().flatten();
+    for (i, driver) in drivers.enumerate() {
+        if let Some(language) = driver.get("language").and_then(Value::as_str) {
+            let path = [key("drivers"), Segment::Index(i), key("language")];
+            if Language::try_from(language).is_err() {
+                problems.push(Problem::new(&path, format!("unknown language `{}`", language), Some("use the name hyperpolyglot detects, e.g. `C++` or `Nim`".to_string())));
+            } else if languages.contains(&language) {
+                problems.push(Problem::new(&path, format!("duplicate driver for `{}`", language), None));
+            }
+            languages.push(language);
+        }
+    }
+
+    let triples = value.get("matrix").and_then(Value::as_array).into_iter().flatten().enumerate()
+        .filter_map(|(i, triple)| Some((vec![key("matrix"), Segment::Index(i)], triple.as_str()?.to_string())));
+    let toolchains = value.get("toolchains").and_then(Value::as_object).into_iter().flatten()
+        .map(|(triple, _)| (vec![key("toolchains"), key(triple)], triple.clone()));
+    for (path, triple) in triples.chain(toolchains) {
+        if triple.split('-').filter(|part| !part.is_empty()).count() < 2 {
+            problems.push(Problem::new(&path, format!("`{}` is not a target triple", triple), Some("write it as <arch>-<vendor>-<os>[-<abi>], e.g. aarch64-unknown-linux-gnu".to_string())));
+        }
+    }
+
+    let mut names = Vec::new();
+    let targets = value.get("targets").and_then(Value::as_array).into_iter().flatten();
+    for (i, target) in targets.enumerate() {
+        if let Some(name) = target.get("name").and_then(Value::as_str) {
+            if names.contains(&name) {
+                problems.push(Problem::new(
+                    &[key("targets"), Segment::Index(i), key("name")],
+                    format!("duplicate target name `{}`", name),
+                    None
+                ));
+            }
+            names.push(name);
+        }
+    }
+    problems
+}
+
+struct Checker<'a> {
+    root: &'a RootSchema,
+    problems: Vec<Problem>
+}
+
+impl Checker<'_> {
+    fn check(&mut self, schema: &Schema, value: &Value, path: &mut Vec<Segment>) {
+        match schema {
+            Schema::Bool(true) => {}
+            Schema::Bool(false) => self.problems.push(Problem::new(path, "value is not allowed here".to_string(), None)),
+            Schema::Object(object) => self.check_object(object, value, path)
+        }
+    }
+
+    fn check_object(&mut self, schema: &SchemaObject, value: &Value, path: &mut Vec<Segment>) {
+        if let Some(reference) = &schema.reference {
+            let name = reference.trim_start_matches("#/definitions/");
+            if let Some(definition) = self.root.definitions.get(name) {
+                self.check(definition, value, path);
+            }
+            return
+        }
+
+        if let Some(subschemas) = &schema.subschemas {
+            for schema in subschemas.all_of.iter().flatten() {
+                self.check(schema, value, path);
+            }
+            for branches in [&subschemas.any_of, &subschemas.one_of].into_iter().flatten() {
+                self.check_branches(branches, value, path);
+            }
+        }
+
+        if let Some(values) = &schema.enum_values {
+            if !values.contains(value) {
+                let expected = values.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(", ");
+                self.problems.push(Problem::new(path, format!("invalid value {} for `{}`", value, KeyPath(path)), Some(format!("expected one of {}", expected))));
+            }
+            return
+        }
+
+        if let Some(types) = &schema.instance_type {
+            let types = match types {
+                SingleOrVec::Single(instance) => vec![**instance],
+                SingleOrVec::Vec(instances) => instances.clone()
+            };
+            if !types.iter().any(|instance| matches_type(*instance, value)) {
+                let expected = types.iter().map(|instance| type_name(*instance)).collect::<Vec<_>>().join(" or ");
+                let hint = match (types.contains(&InstanceType::Array), value) {
+                    (true, Value::String(_)) => Some("wrap the value in a list: [ ... ]".to_string()),
+                    _ => None
+                };
+                self.problems.push(Problem::new(path, format!("`{}` should be {}, found {}", KeyPath(path), expected, value_name(value)), hint));
+                return
+            }
+        }
+
+        if let (Some(object), Value::Object(map)) = (&schema.object, value) {
+            for required in &object.required {
+                if !map.contains_key(required) {
+                    self.problems.push(Problem::new(path, format!("missing required key `{}`", required), None));
+                }
+            }
+            for (key, item) in map {
+                path.push(Segment::Key(key.clone()));
+                match (object.properties.get(key), &object.additional_properties) {
+                    (Some(schema), _) => self.check(schema, item, path),
+                    (None, Some(additional)) if **additional == Schema::Bool(false) => {
+                        let hint = closest(key, object.properties.keys())
+                            .map(|known| format!("did you mean `{}`?", known))
+                            .or_else(|| Some(format!("known keys: {}", object.properties.keys().cloned().collect::<Vec<_>>().join(", "))));
+                        self.problems.push(Problem::new(path, format!("unknown key `{}`", key), hint));
+                    }
+                    (None, Some(additional)) => self.check(additional, item, path),
+                    (None, None) => {}
+                }
+                path.pop();
+            }
+        }
+
+        if let (Some(array), Value::Array(items)) = (&schema.array, value) {
+            for (i, item) in items.iter().enumerate() {
+                let schema = match &array.items {
+                    Some(SingleOrVec::Single(schema)) => Some(&**schema),
+                    Some(SingleOrVec::Vec(schemas)) => schemas.get(i),
+                    None => None
+                };
+                if let Some(schema) = schema {
+                    path.push(Segment::Index(i));
+                    self.check(schema, item, path);
+                    path.pop();
+                }
+            }
+        }
+    }
+
+    /// Reports the branch that got furthest when none of `anyOf`/`oneOf` accepts the value.
+    fn check_branches(&mut self, branches: &[Schema], value: &Value, path: &mut [Segment]) {
+        // Enums whose variants are documented become one branch per value, listed together.
+        let values = branches.iter().map(|branch| match branch {
+            Schema::Object(SchemaObject { enum_values: Some(values), .. }) => Some(values.iter()),
+            _ => None
+        }).collect::<Option<Vec<_>>>();
+        if let Some(values) = values {
+            let values = values.into_iter().flatten().collect::<Vec<_>>();
+            if !values.contains(&value) {
+                let expected = values.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(", ");
+                self.problems.push(Problem::new(path, format!("invalid value {} for `{}`", value, KeyPath(path)), Some(format!("expected one of {}", expected))));
+            }
+            return
+        }
+
+        let mut best: Option<Vec<Problem>> = None;
+        for branch in branches {
+            let mut checker = Checker { root: self.root, problems: Vec::new() };
+            checker.check(branch, value, &mut path.to_vec());
+            if checker.problems.is_empty() {
+                return
+            }
+            let rank = |problems: &Vec<Problem>| (problems.iter().any(|p| p.path.len() == path.len()), problems.len());
+            if best.as_ref().is_none_or(|best| rank(&checker.problems) < rank(best)) {
+                best = Some(checker.problems);
+            }
+        }
+        self.problems.extend(best.unwrap_or_default());
+    }
+}
+
+/// The name of a `hooks` entry, given alone or with its phase.
+fn hook_name(entry: &Value) -> Option<&str> {
+    entry.as_str().or_else(|| entry.get("name").and_then(Value::as_str))
+}
+
+fn matches_type(instance: InstanceType, value: &Value) -> bool {
+    match instance {
+        InstanceType::Null => value.is_null(),
+        InstanceType::Boolean => value.is_boolean(),
+        InstanceType::Object => value.is_object(),
+        InstanceType::Array => value.is_array(),
+        InstanceType::Number => value.is_number(),
+        InstanceType::String => value.is_string(),
+        InstanceType::Integer => value.is_i64() || value.is_u64()
+    }
+}
+
+fn type_name(instance: InstanceType) -> &'static str {
+    match instance {
+        InstanceType::Null => "null",
+        InstanceType::Boolean => "a boolean",
+        InstanceType::Object => "a table",
+        InstanceType::Array => "a list",
+        InstanceType::Number => "a number",
+        InstanceType::String => "a string",
+        InstanceType::Integer => "an integer"
+    }
+}
+
+fn value_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "a list",
+        Value::Object(_) => "a table"
+    }
+}
+
+fn closest<'a>(key: &str, known: impl Iterator<Item = &'a String>) -> Option<&'a String> {
+    known
+        .map(|candidate| (distance(key, candidate), candidate))
+        .filter(|(distance, candidate)| *distance <= candidate.len() / 3 + 1)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            current.push((previous[j] + cost).min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+pub fn line_column(content: &str, offset: usize) -> (usize, usize) {
+    let before = &content[..offset.min(content.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().unwrap_or_default().chars().count() + 1;
+    (line, column)
+}
+
+/// Finds the byte offset of the deepest part of `path` present in the source text.
+pub fn locate(content: &str, format: Format, path: &[Segment]) -> usize {
+    match format {
+        Format::Json => locate_json(content, path),
+        Format::Toml => locate_toml(content, path),
+        Format::Yaml => locate_yaml(content, path)
+    }
+}
+
+fn locate_json(content: &str, path: &[Segment]) -> usize {
+    let mut scanner = JsonScanner { bytes: content.as_bytes(), pos: 0, spans: Vec::new() };
+    scanner.value(&mut Vec::new());
+    (0..=path.len()).rev()
+        .find_map(|depth| scanner.spans.iter().find(|(span, _)| span == &path[..depth]).map(|(_, offset)| *offset))
+        .unwrap_or(0)
+}
+
+/// Records the offset of every key and array element of an already valid JSON document.
+struct JsonScanner<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    spans: Vec<(Vec<Segment>, usize)>
+}
+
+impl JsonScanner<'_> {
+    fn skip_whitespace(&mut self) {
+        while self.pos < self.bytes.len() && self.bytes[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&self) -> u8 {
+        self.bytes.get(self.pos).copied().unwrap_or(0)
+    }
+
+    fn string(&mut self) -> String {
+        let start = self.pos + 1;
+        self.pos += 1;
+        while self.pos < self.bytes.len() && self.bytes[self.pos] != b'"' {
+            self.pos += if self.bytes[self.pos] == b'\\' { 2 } else { 1 };
+        }
+        let raw = &self.bytes[start..self.pos.min(self.bytes.len())];
+        self.pos += 1;
+        serde_json::from_slice(&[b"\"", raw, b"\""].concat()).unwrap_or_default()
+    }
+
+    fn value(&mut self, path: &mut Vec<Segment>) {
+        self.skip_whitespace();
+        match self.peek() {
+            b'{' => {
+                self.pos += 1;
+                while self.pos < self.bytes.len() {
+                    self.skip_whitespace();
+                    if self.peek() == b'}' {
+                        break
+                    }
+                    let start = self.pos;
+                    path.push(Segment::Key(self.string()));
+                    self.spans.push((path.clone(), start));
+                    self.skip_whitespace();
+                    self.pos += 1;
+                    self.value(path);
+                    path.pop();
+                    self.skip_whitespace();
+                    if self.peek() == b',' {
+                        self.pos += 1;
+                    }
+                }
+                self.pos += 1;
+            }
+            b'[' => {
+                self.pos += 1;
+                let mut index = 0;
+                while self.pos < self.bytes.len() {
+                    self.skip_whitespace();
+                    if self.peek() == b']' {
+                        break
+                    }
+                    path.push(Segment::Index(index));
+                    self.spans.push((path.clone(), self.pos));
+                    self.value(path);
+                    path.pop();
+                    self.skip_whitespace();
+                    if self.peek() == b',' {
+                        self.pos += 1;
+                    }
+                    index += 1;
+                }
+                self.pos += 1;
+            }
+            b'"' => {
+                self.string();
+            }
+            _ => {
+                while self.pos < self.bytes.len() && !b",]} \t\r\n".contains(&self.bytes[self.pos]) {
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+}
+
+fn locate_toml(content: &str, path: &[Segment]) -> usize {
+    match ImDocument::parse(content) {
+        Ok(document) => toml_table(document.as_table(), path).unwrap_or(0),
+        Err(_) => 0
+    }
+}
+
+fn toml_table(table: &dyn TableLike, path: &[Segment]) -> Option<usize> {
+    let Some(Segment::Key(key)) = path.first() else { return None };
+    let (key, item) = table.get_key_value(key)?;
+    let here = key.span().or_else(|| item.span()).map(|span| span.start);
+    toml_item(item, &path[1..]).or(here)
+}
+
+fn toml_item(item: &Item, path: &[Segment]) -> Option<usize> {
+    match path.first()? {
+        Segment::Key(_) => toml_table(item.as_table_like()?, path),
+        Segment::Index(index) => {
+            if let Some(tables) = item.as_array_of_tables() {
+                let table = tables.get(*index)?;
+                return toml_table(table, &path[1..]).or(table.span().map(|span| span.start))
+            }
+            let value = item.as_array()?.get(*index)?;
+            let here = value.span().map(|span| span.start);
+            match (value.as_inline_table(), path.get(1)) {
+                (Some(table), Some(_)) => toml_table(table, &path[1..]).or(here),
+                _ => here
+            }
+        }
+    }
+}
+
+/// Best-effort locator for block-style YAML; flow collections resolve to their parent key.
+fn locate_yaml(content: &str, path: &[Segment]) -> usize {
+    // Every line split into its `- ` markers and the content after them, with byte offsets.
+    let mut entries: Vec<(usize, usize, bool, &str)> = Vec::new();
+    let mut offset = 0;
+    for line in content.split_inclusive('\n') {
+        let mut column = line.len() - line.trim_start().len();
+        let mut rest = line.trim();
+        if !rest.is_empty() && !rest.starts_with('#') {
+            while rest == "-" || rest.starts_with("- ") {
+                entries.push((offset + column, column, true, "-"));
+                let after = rest[1..].trim_start();
+                column += rest.len() - after.len();
+                rest = after;
+            }
+            if !rest.is_empty() {
+                entries.push((offset + column, column, false, rest));
+            }
+        }
+        offset += line.len();
+    }
+
+    // Sequences may sit at the same column as their key, so keys and dashes end a block differently.
+    let (mut start, mut floor, mut key_floor, mut found) = (0, 0, None, 0);
+    for segment in path {
+        let mut level = None;
+        let mut count = 0;
+        let mut hit = None;
+        for (i, (at, column, dash, text)) in entries.iter().enumerate().skip(start) {
+            if *column < floor || (!dash && key_floor.is_some_and(|key_floor| *column <= key_floor)) {
+                break
+            }
+            match segment {
+                Segment::Key(key) if !dash => {
+                    let level = *level.get_or_insert(*column);
+                    let unquoted = text.trim_start_matches(['"', '\'']);
+                    if *column == level && unquoted.starts_with(key.as_str()) && unquoted[key.len()..].trim_start_matches(['"', '\'']).starts_with(':') {
+                        hit = Some((i, *at, *column));
+                        break
+                    }
+                }
+                Segment::Index(index) if *dash => {
+                    let level = *level.get_or_insert(*column);
+                    if *column == level {
+                        if count == *index {
+                            hit = Some((i, *at, *column));
+                            break
+                        }
+                        count += 1;
+                    }
+                }
+                _ => {}
+            }
+        }
+        match (hit, segment) {
+            (Some((i, at, column)), Segment::Key(_)) => {
+                (start, floor, key_floor, found) = (i + 1, column, Some(column), at);
+            }
+            (Some((i, at, column)), Segment::Index(_)) => {
+                (start, floor, key_floor, found) = (i + 1, column + 1, None, at);
+            }
+            (None, _) => break
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(name: &str) -> Segment {
+        Segment::Key(name.to_string())
+    }
+
+    fn position(content: &str, format: Format, path: &[Segment]) -> (usize, usize) {
+        line_column(content, locate(content, format, path))
+    }
+
+    #[test]
+    fn counts_lines_and_characters() {
+        let content = "ab\nçd\n";
+        assert_eq!(line_column(content, 0), (1, 1));
+        assert_eq!(line_column(content, 3), (2, 1));
+        assert_eq!(line_column(content, 5), (2, 2));
+        assert_eq!(line_column(content, 100), (3, 1));
+    }
+
+    #[test]
+    fn locates_json_keys_and_elements() {
+        let content = "{\n  \"name\": \"app\",\n  \"targets\": [\n    { \"name\": \"lib\" },\n    { \"kind\": 3 }\n  ]\n}\n";
+        assert_eq!(position(content, Format::Json, &[key("name")]), (2, 3));
+        assert_eq!(position(content, Format::Json, &[key("targets"), Segment::Index(1)]), (5, 5));
+        assert_eq!(position(content, Format::Json, &[key("targets"), Segment::Index(1), key("kind")]), (5, 7));
+        assert_eq!(position(content, Format::Json, &[key("targets"), Segment::Index(1), key("sources")]), (5, 5));
+    }
+
+    #[test]
+    fn locates_toml_keys_and_tables() {
+        let content = "name = \"app\"\n\n[[targets]]\nname = \"lib\"\n\n[[targets]]\nkind = 3\nflags = [\"-O2\", 4]\n";
+        assert_eq!(position(content, Format::Toml, &[key("name")]), (1, 1));
+        assert_eq!(position(content, Format::Toml, &[key("targets"), Segment::Index(1), key("kind")]), (7, 1));
+        assert_eq!(position(content, Format::Toml, &[key("targets"), Segment::Index(1), key("flags"), Segment::Index(1)]), (8, 17));
+        assert_eq!(position(content, Format::Toml, &[key("version")]), (1, 1));
+    }
+
+    #[test]
+    fn locates_yaml_keys_and_list_items() {
+        let content = "name: app\ntargets:\n  - name: lib\n  - name: tool\n    kind: 3\n    flags:\n      - -O2\n      - 4\n";
+        assert_eq!(position(content, Format::Yaml, &[key("targets")]), (2, 1));
+        assert_eq!(position(content, Format::Yaml, &[key("targets"), Segment::Index(1), key("kind")]), (5, 5));
+        assert_eq!(position(content, Format::Yaml, &[key("targets"), Segment::Index(1), key("flags"), Segment::Index(1)]), (8, 7));
+        assert_eq!(position(content, Format::Yaml, &[key("targets"), Segment::Index(0), key("kind")]), (3, 3));
+    }
+}
@@ -0,0 +1,114 @@
+use std::{collections::HashMap, path::PathBuf, process};
+use clap::ArgMatches;
+use log::{error, info};
+use owo_colors::{OwoColorize, Stream::Stdout};
+
+use crate::{config::{self, Format, Project}, graph, profiles};
+
+/// Resolves the projects a command runs on, in dependency order.
+///
+/// At a workspace root that is every member followed by the root itself, inside a member it is
+/// the member and the members it depends on, and `--package` picks a member the same way.
+/// Outside of a workspace it is just `project`. Exits the process when the workspace is broken.
+pub fn select(project: Project, matches: &ArgMatches) -> Vec<Project> {
+    let package = matches.get_one::<String>("package");
+    let profile = matches.get_one::<String>("profile").map(|v| v.as_str()).unwrap_or(profiles::DEFAULT_PROFILE);
+
+    let (root, current) = if project.config.workspace.is_some() {
+        (project, None)
+    } else {
+        match find_root(&project, profile) {
+            Some(root) => (root, Some(project.root)),
+            None => {
+                if let Some(package) = package {
+                    fail(format!("Cannot select package {}: {} is not part of a workspace.", package, project.root.display()));
+                }
+                return vec![project]
+            }
+        }
+    };
+    info!("Workspace root: {}", root.root.display());
+
+    let workspace = root.config.workspace.clone().unwrap_or_default();
+    let mut members = Vec::new();
+    for member in &workspace.members {
+        let dir = root.root.join(&member.path);
+        match config::find_in(&dir) {
+            Some(path) => members.push(config::open(&path, profile)),
+            None => fail(format!("Workspace member {} has no configuration file.", dir.display()))
+        }
+    }
+
+    let mut names = HashMap::new();
+    for (i, member) in members.iter().enumerate() {
+        if names.insert(member.config.name.clone(), i).is_some() {
+            fail(format!("Two workspace members are named {}.", member.config.name));
+        }
+    }
+
+    let mut deps = Vec::new();
+    for (member, project) in workspace.members.iter().zip(&members) {
+        let mut edges = Vec::new();
+        for dependency in &member.depends_on {
+            match names.get(dependency) {
+                Some(i) => edges.push(*i),
+                None => fail(format!("{} depends on {}, which is not a workspace member.", project.config.name, dependency))
+            }
+        }
+        deps.push(edges);
+    }
+
+    let order = match graph::order(&deps) {
+        Ok(order) => order,
+        Err(cycle) => {
+            let cycle = cycle.iter().map(|i| members[*i].config.name.as_str()).collect::<Vec<_>>();
+            fail(format!("Workspace members depend on each other: {}", cycle.join(" -> ")))
+        }
+    };
+
+    let roots: Vec<usize> = match (package, &current) {
+        (Some(package), _) => match names.get(package) {
+            Some(i) => vec![*i],
+            None => fail(format!("No workspace member named {}.", package))
+        },
+        (None, Some(current)) => members.iter().position(|member| &member.root == current).into_iter().collect(),
+        (None, None) => (0..members.len()).collect()
+    };
+    let selected = graph::closure(&deps, &roots);
+
+    let mut members: Vec<Option<Project>> = members.into_iter().map(Some).collect();
+    let mut projects: Vec<Project> = order.into_iter()
+        .filter(|i| selected[*i])
+        .filter_map(|i| members[i].take())
+        .collect();
+    let whole = package.is_none() && current.is_none();
+    if whole && (!root.config.targets.is_empty() || !root.config.hooks.is_empty()) {
+        projects.push(root);
+    }
+    projects
+}
+
+/// The nearest workspace above `project` that lists it as a member.
+fn find_root(project: &Project, profile: &str) -> Option<Project> {
+    for dir in project.root.ancestors().skip(1) {
+        let Some(path) = config::find_in(dir) else { continue };
+        let format = Format::from_path(&path).unwrap_or(Format::Json);
+        let Ok(value) = config::read_value(&path, format) else { continue };
+        let Some(members) = value.get("workspace").and_then(|workspace| workspace.get("members")).and_then(|members| members.as_array()) else { continue };
+
+        let listed = members.iter()
+            .filter_map(|member| member.get("path").and_then(|path| path.as_str()))
+            .filter_map(|member| dir.join(member).canonicalize().ok())
+            .any(|member: PathBuf| member == project.root);
+        if listed {
+            return Some(config::open(&path, profile))
+        }
+    }
+    None
+}
+
+fn fail(message: String) -> ! {
+    error!("{}", message);
+    println!("{}", message.if_supports_color(Stdout, |text| text.red()));
+    process::exit(7);
+}